@@ -5,11 +5,105 @@ type ResultProps = {
   result: any;
 };
 
+const statusIcons: { [status: string]: string } = {
+  passed: "✅",
+  failed: "❌",
+  skipped: "⏭️",
+};
+
+const GradeReport: React.FC<{ report: any }> = ({ report }) => {
+  return (
+    <div>
+      <p className="font-bold">{report.message}</p>
+      <ul className="mt-2">
+        {report.steps.map((step: any) => (
+          <li key={step.name}>
+            {statusIcons[step.status]} nargo {step.name}
+            {step.status !== "skipped" && (
+              <span className="text-sm text-gray-500">
+                {" "}
+                ({step.duration_ms} ms)
+              </span>
+            )}
+            {step.status === "failed" && (
+              <pre className="text-sm whitespace-pre-wrap">
+                {step.stderr || step.stdout}
+              </pre>
+            )}
+          </li>
+        ))}
+        {report.vectors.map((vector: any) => (
+          <li key={vector.name}>
+            {vector.passed ? statusIcons.passed : statusIcons.failed} Test
+            vector &quot;{vector.name}&quot;: {vector.detail}
+          </li>
+        ))}
+      </ul>
+    </div>
+  );
+};
+
+const LiveProgress: React.FC<{ progress: any }> = ({ progress }) => {
+  return (
+    <div>
+      <ul>
+        {progress.steps.map((step: any) => (
+          <li key={step.name}>
+            {statusIcons[step.status]} nargo {step.name}
+          </li>
+        ))}
+        {progress.current && (
+          <li>
+            ⏳ nargo {progress.current.name}
+            <pre className="text-sm whitespace-pre-wrap">
+              {progress.current.lines.join("\n")}
+            </pre>
+          </li>
+        )}
+      </ul>
+    </div>
+  );
+};
+
+const TestReport: React.FC<{ report: any }> = ({ report }) => {
+  return (
+    <div>
+      <p className="font-bold">{report.message}</p>
+      <ul className="mt-2">
+        {report.tests.map((test: any) => (
+          <li key={test.name}>
+            {statusIcons[test.status]} {test.name}
+            {test.output.length > 0 && (
+              <pre className="text-sm whitespace-pre-wrap">
+                {test.output.join("\n")}
+              </pre>
+            )}
+            {test.failure_message && (
+              <pre className="text-sm whitespace-pre-wrap">
+                {test.failure_message}
+              </pre>
+            )}
+          </li>
+        ))}
+      </ul>
+      {report.tests.length === 0 && report.stderr && (
+        <pre className="text-sm whitespace-pre-wrap">{report.stderr}</pre>
+      )}
+    </div>
+  );
+};
+
 const Result: React.FC<ResultProps> = ({ result }) => {
   return (
     <div>
       {result ? (
-        result.message ? (
+        result.live ? (
+          <LiveProgress progress={result} />
+        ) : result.steps ? (
+          <GradeReport report={result} />
+        ) : result.tests ? (
+          <TestReport report={result} />
+        ) : result.message ? (
           typeof result.message === "object" ? (
             JSON.stringify(result.message, null, 2)
           ) : (
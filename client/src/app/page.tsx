@@ -22,11 +22,24 @@ export default function Home() {
   const [verifierToml, setVerifierToml] = useState("");
   const [result, setResult] = useState("");
   const [challenge, setChallenge] = useState("1");
+  const [challengeList, setChallengeList] = useState<
+    Array<{ id: number; title: string }>
+  >([]);
   const [isRunning, setIsRunning] = useState(false);
   const [isOpen, setIsOpen] = useState(false);
 
   const server_url = process.env.NEXT_PUBLIC_SERVER_URL;
 
+  useEffect(() => {
+    async function fetchChallenges() {
+      const response = await fetch(server_url + "challenges");
+      const data = await response.json();
+      setChallengeList(data);
+    }
+
+    fetchChallenges();
+  }, [server_url]);
+
   useEffect(() => {
     async function fetchData() {
       if (!challenge) return;
@@ -34,6 +47,7 @@ export default function Home() {
       const response = await fetch(server_url + "challenges/" + challenge);
       const data = await response.json();
       setInstructions(data.description);
+      setCode(data.starter_code);
     }
 
     fetchData();
@@ -60,8 +74,8 @@ export default function Home() {
     };
     console.log(data);
 
-    console.log(data);
-    const response = await fetch(`${server_url}execute`, {
+    // Run the submission as a job and follow its progress as nargo runs
+    const response = await fetch(`${server_url}jobs`, {
       method: "POST",
       mode: "cors",
       headers: {
@@ -70,9 +84,46 @@ export default function Home() {
       body: JSON.stringify(data),
     });
 
-    const result = await response.json(); // get the response data as json
-    setIsRunning(false);
-    setResult(result); // set result to state
+    const job = await response.json();
+    if (!response.ok) {
+      setIsRunning(false);
+      setResult(job);
+      return;
+    }
+
+    const progress: { live: boolean; steps: any[]; current: any } = {
+      live: true,
+      steps: [],
+      current: null,
+    };
+    setResult({ ...progress });
+    const events = new EventSource(`${server_url}jobs/${job.id}/events`);
+    events.addEventListener("step-start", (event: MessageEvent) => {
+      progress.current = { ...JSON.parse(event.data), lines: [] };
+      setResult({ ...progress });
+    });
+    const addLine = (event: MessageEvent) => {
+      if (!progress.current) return;
+      progress.current.lines.push(JSON.parse(event.data).line);
+      setResult({ ...progress });
+    };
+    events.addEventListener("stdout", addLine);
+    events.addEventListener("stderr", addLine);
+    events.addEventListener("step-finish", (event: MessageEvent) => {
+      progress.steps.push(JSON.parse(event.data));
+      progress.current = null;
+      setResult({ ...progress });
+    });
+    events.addEventListener("done", (event: MessageEvent) => {
+      events.close();
+      const finished = JSON.parse(event.data);
+      setIsRunning(false);
+      setResult(finished.result ?? { message: finished.error?.message ?? finished.status });
+    });
+    events.onerror = () => {
+      events.close();
+      setIsRunning(false);
+    };
   };
 
   const testCode = async () => {
@@ -154,9 +205,11 @@ export default function Home() {
           className="p-2 w-full focus:outline-none"
         >
           <option value="">Select challenge...</option>
-          <option value="1">Challenge 1</option>
-          <option value="2">Challenge 2</option>
-          <option value="3">Challenge 3</option>
+          {challengeList.map((c) => (
+            <option key={c.id} value={String(c.id)}>
+              Challenge {c.id}: {c.title}
+            </option>
+          ))}
         </select>
       </div>
 
@@ -0,0 +1,165 @@
+use crate::cache::hash_parts;
+use crate::report::StepReport;
+use crate::sandbox::env_u64;
+use crate::template::copy_dir;
+use std::io;
+use std::path::{ Path, PathBuf };
+use std::time::SystemTime;
+use uuid::Uuid;
+
+// Compiled circuits kept on disk, for submissions whose source was compiled before and only their
+// prover inputs changed. An artifact is the project's `target` directory, which holds the
+// program's ACIR and ABI along with any keys nargo saved, plus the reports of the test and check
+// steps, which only depend on the source. Those steps are not run again, while prove and verify
+// still run nargo: it is nargo that skips compiling when the program restored into `target` was
+// built from the same source by the same version. Artifacts are keyed by a hash of the project's
+// source files and the version of nargo. Configured with environment variables:
+// - ARTIFACT_CACHE_DIR: where artifacts are kept (default tmp/artifacts, under the current
+//   directory)
+// - ARTIFACT_CACHE_SIZE: artifacts kept, the least recently used going first, 0 to turn the
+//   cache off (default 256)
+pub struct ArtifactCache {
+    dir: PathBuf,
+    toolchain_version: String,
+    capacity: usize,
+}
+
+impl ArtifactCache {
+    pub fn new(dir: &Path, toolchain_version: &str, capacity: usize) -> Self {
+        ArtifactCache {
+            dir: dir.to_path_buf(),
+            toolchain_version: toolchain_version.to_string(),
+            capacity,
+        }
+    }
+
+    // Function to create the cache configured by the environment, for the given version of nargo
+    pub fn from_env(toolchain_version: &str) -> io::Result<Self> {
+        let dir = match std::env::var("ARTIFACT_CACHE_DIR") {
+            Ok(dir) => PathBuf::from(dir),
+            Err(_) => std::env::current_dir()?.join("tmp/artifacts"),
+        };
+        let capacity = env_u64("ARTIFACT_CACHE_SIZE", 256) as usize;
+        Ok(ArtifactCache::new(&dir, toolchain_version, capacity))
+    }
+
+    // Function to get the key of a project's artifact, from its Nargo.toml and source files
+    pub async fn key(&self, project: &Path) -> io::Result<String> {
+        let mut parts = vec![
+            self.toolchain_version.as_bytes().to_vec(),
+            tokio::fs::read(project.join("Nargo.toml")).await?
+        ];
+        for file in source_files(&project.join("src")).await? {
+            let name = file.strip_prefix(project).unwrap_or(&file);
+            parts.push(name.to_string_lossy().as_bytes().to_vec());
+            parts.push(tokio::fs::read(&file).await?);
+        }
+        Ok(hash_parts(parts))
+    }
+
+    // Function to copy an artifact's `target` directory into the project, returning the reports
+    // of the steps that compiled it, or None if there is no such artifact
+    pub async fn restore(&self, key: &str, project: &Path) -> Option<Vec<StepReport>> {
+        if self.capacity == 0 {
+            return None;
+        }
+        let entry = self.dir.join(key);
+        let steps_path = entry.join("steps.json");
+        let steps = tokio::fs::read(&steps_path).await.ok()?;
+        let mut steps: Vec<StepReport> = serde_json::from_slice(&steps).ok()?;
+
+        let target = project.join("target");
+        let _ = tokio::fs::remove_dir_all(&target).await;
+        if tokio::fs::metadata(entry.join("target")).await.is_ok() {
+            // The artifact may have been evicted while it was being copied
+            if let Err(e) = copy_dir(&entry.join("target"), &target).await {
+                eprintln!("Failed to restore artifact {}: {}", key, e);
+                let _ = tokio::fs::remove_dir_all(&target).await;
+                return None;
+            }
+        }
+
+        // Mark the artifact as used, so it is evicted last
+        if let Ok(file) = std::fs::File::options().write(true).open(&steps_path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        for step in &mut steps {
+            step.cached = true;
+        }
+        Some(steps)
+    }
+
+    // Function to keep the project's `target` directory and the reports of the steps that
+    // compiled it, evicting the least recently used artifacts if there are too many
+    pub async fn save(&self, key: &str, project: &Path, steps: &[StepReport]) -> io::Result<()> {
+        let entry = self.dir.join(key);
+        if self.capacity == 0 || tokio::fs::metadata(&entry).await.is_ok() {
+            return Ok(());
+        }
+
+        // Build the artifact beside the cache and move it in, so it is never seen half written
+        let partial = self.dir.join(format!(".{}-{}", key, Uuid::new_v4()));
+        tokio::fs::create_dir_all(&partial).await?;
+        let built = async {
+            if tokio::fs::metadata(project.join("target")).await.is_ok() {
+                copy_dir(&project.join("target"), &partial.join("target")).await?;
+            }
+            let steps = serde_json::to_vec(steps).map_err(io::Error::other)?;
+            tokio::fs::write(partial.join("steps.json"), steps).await?;
+            tokio::fs::rename(&partial, &entry).await
+        }.await;
+        if built.is_err() {
+            // Another request may have saved the same artifact first
+            let _ = tokio::fs::remove_dir_all(&partial).await;
+            if tokio::fs::metadata(&entry).await.is_ok() {
+                return Ok(());
+            }
+            return built;
+        }
+
+        self.evict().await
+    }
+
+    // Function to remove the least recently used artifacts while there are too many
+    async fn evict(&self) -> io::Result<()> {
+        let mut artifacts = vec![];
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let used = match tokio::fs::metadata(entry.path().join("steps.json")).await {
+                Ok(metadata) => metadata.modified()?,
+                Err(_) => SystemTime::UNIX_EPOCH,
+            };
+            artifacts.push((used, entry.path()));
+        }
+        if artifacts.len() <= self.capacity {
+            return Ok(());
+        }
+        artifacts.sort();
+        let excess = artifacts.len() - self.capacity;
+        for (_, path) in artifacts.into_iter().take(excess) {
+            tokio::fs::remove_dir_all(&path).await?;
+        }
+        Ok(())
+    }
+}
+
+// Function to list the files under a directory, sorted so they always hash in the same order
+async fn source_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let mut entries = tokio::fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_type().await?.is_dir() {
+                pending.push(entry.path());
+            } else {
+                files.push(entry.path());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
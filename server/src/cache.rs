@@ -0,0 +1,245 @@
+use crate::challenges::Challenge;
+use crate::inputs::table_toml;
+use crate::sandbox::env_u64;
+use crate::util::lock;
+use serde_derive::Serialize;
+use sha2::{ Digest, Sha256 };
+use std::collections::HashMap;
+use std::sync::{ Arc, Mutex };
+use std::time::{ Duration, Instant };
+use tokio::sync::watch;
+
+// Results of runs that were already done, so resubmitting the same code and inputs does not run
+// nargo again. Results are keyed by a hash of everything that decides them: the step, the code,
+// the challenge and the version of its test cases, the extra inputs of the step and the version
+// of nargo. Only results are kept, not errors such as hitting a resource limit. A request for a
+// result that is already being worked out waits for it instead of running nargo as well.
+// Configured with environment variables:
+// - RESULT_CACHE_SIZE: results kept, the least recently used going first, 0 to turn the cache off
+//   (default 1024)
+// - RESULT_CACHE_TTL_SECONDS: how long a result is kept (default 3600)
+pub struct ResultCache {
+    toolchain_version: String,
+    capacity: usize,
+    ttl: Duration,
+    state: Mutex<CacheState>,
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<String, Entry>,
+    // Results being worked out, which are sent to whoever is waiting for them once they are done
+    in_flight: HashMap<String, watch::Receiver<Option<serde_json::Value>>>,
+}
+
+struct Entry {
+    value: serde_json::Value,
+    stored_at: Instant,
+    last_used: Instant,
+}
+
+// Where a result came from, as told to the caller in the X-Cache header
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheStatus {
+    // nargo was run for this request
+    Miss,
+    // The result was already cached
+    Hit,
+    // The result was worked out for an identical request that was running at the same time
+    Shared,
+}
+
+// A result that did not need nargo to be run
+pub struct Cached {
+    pub value: serde_json::Value,
+    pub status: CacheStatus,
+    // How long ago the result was worked out
+    pub age: Duration,
+}
+
+// The outcome of looking a key up: either its result, or a turn to work it out
+pub enum Lookup {
+    Cached(Cached),
+    Run(Pending),
+}
+
+// A result the caller has to work out. Requests for the same key wait until it is stored, or try
+// again themselves if it is dropped without a result, for example because nargo hit a limit.
+pub struct Pending {
+    cache: Arc<ResultCache>,
+    key: String,
+    sender: Option<watch::Sender<Option<serde_json::Value>>>,
+}
+
+impl ResultCache {
+    pub fn new(toolchain_version: &str, capacity: usize, ttl: Duration) -> Self {
+        ResultCache {
+            toolchain_version: toolchain_version.to_string(),
+            capacity,
+            ttl,
+            state: Mutex::default(),
+        }
+    }
+
+    // Function to create the cache configured by the environment, for the given version of nargo
+    pub fn from_env(toolchain_version: &str) -> Self {
+        ResultCache::new(
+            toolchain_version,
+            env_u64("RESULT_CACHE_SIZE", 1024) as usize,
+            Duration::from_secs(env_u64("RESULT_CACHE_TTL_SECONDS", 3600))
+        )
+    }
+
+    // Function to get the key of a step's result. `inputs` is anything else the step depends
+    // on, such as the prover inputs or the name of the tests to run.
+    pub fn key(&self, step: &str, challenge: &Challenge, code: &str, inputs: &str) -> String {
+        let version = challenge_version(challenge);
+        let parts: [&[u8]; 6] = [
+            step.as_bytes(),
+            self.toolchain_version.as_bytes(),
+            &challenge.id.to_le_bytes(),
+            version.as_bytes(),
+            code.as_bytes(),
+            inputs.as_bytes(),
+        ];
+        hash_parts(parts)
+    }
+
+    // Function to get a result that is already cached, without waiting for one that an
+    // identical request is working out
+    pub fn cached(&self, key: &str) -> Option<Cached> {
+        self.hit(&mut lock(&self.state), key)
+    }
+
+    fn hit(&self, state: &mut CacheState, key: &str) -> Option<Cached> {
+        let now = Instant::now();
+        let ttl = self.ttl;
+        state.entries.retain(|_, entry| now.duration_since(entry.stored_at) < ttl);
+        let entry = state.entries.get_mut(key)?;
+        entry.last_used = now;
+        Some(Cached {
+            value: entry.value.clone(),
+            status: CacheStatus::Hit,
+            age: now.duration_since(entry.stored_at),
+        })
+    }
+
+    // Function to look up a result, waiting for it if an identical request is working it out
+    pub async fn lookup(self: &Arc<Self>, key: &str) -> Lookup {
+        loop {
+            let mut receiver = {
+                let mut state = lock(&self.state);
+                if let Some(cached) = self.hit(&mut state, key) {
+                    return Lookup::Cached(cached);
+                }
+
+                match state.in_flight.get(key) {
+                    Some(receiver) => receiver.clone(),
+                    None => {
+                        let sender = if self.capacity > 0 {
+                            let (sender, receiver) = watch::channel(None);
+                            state.in_flight.insert(key.to_string(), receiver);
+                            Some(sender)
+                        } else {
+                            None
+                        };
+                        return Lookup::Run(Pending {
+                            cache: Arc::clone(self),
+                            key: key.to_string(),
+                            sender,
+                        });
+                    }
+                }
+            };
+
+            // Wait for the identical request to finish. If it gives up without a result, look
+            // the key up again, which may make this request the one to work it out.
+            loop {
+                if let Some(value) = receiver.borrow().clone() {
+                    return Lookup::Cached(Cached {
+                        value,
+                        status: CacheStatus::Shared,
+                        age: Duration::ZERO,
+                    });
+                }
+                if receiver.changed().await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+impl Pending {
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    // Function to cache the result and send it to the requests waiting for it
+    pub fn store(mut self, value: &serde_json::Value) {
+        let Some(sender) = self.sender.take() else {
+            return;
+        };
+        let mut state = lock(&self.cache.state);
+        state.in_flight.remove(&self.key);
+        if state.entries.len() >= self.cache.capacity {
+            let oldest = state.entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                state.entries.remove(&oldest);
+            }
+        }
+        let now = Instant::now();
+        state.entries.insert(self.key.clone(), Entry {
+            value: value.clone(),
+            stored_at: now,
+            last_used: now,
+        });
+        // Sending only fails when nobody is waiting
+        let _ = sender.send(Some(value.clone()));
+    }
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        // Dropping the sender tells anyone waiting that there is no result
+        if self.sender.take().is_some() {
+            lock(&self.cache.state).in_flight.remove(&self.key);
+        }
+    }
+}
+
+// Function to get a version of a challenge's test cases, helpers and test vectors, which changes
+// whenever one of them does
+fn challenge_version(challenge: &Challenge) -> String {
+    // Each list is prefixed with its length, so items cannot move from one list to another
+    let mut parts = vec![challenge.test_cases.len().to_string()];
+    parts.extend(challenge.test_cases.iter().cloned());
+    parts.push(challenge.helpers.len().to_string());
+    for helper in &challenge.helpers {
+        parts.push(helper.name.clone());
+        parts.push(helper.code.clone());
+    }
+    for vector in &challenge.vectors {
+        parts.push(vector.name.clone());
+        parts.push(format!("{:?}", vector.expect));
+        parts.push(table_toml(&vector.inputs).unwrap_or_default());
+        parts.push(vector.return_value.as_ref().map(|value| value.to_string()).unwrap_or_default());
+    }
+    hash_parts(parts)
+}
+
+// Function to get the SHA-256 of a list of parts, in hex. Each part is prefixed with its length,
+// so parts cannot run into each other.
+pub fn hash_parts<I>(parts: I) -> String where I: IntoIterator, I::Item: AsRef<[u8]> {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        let bytes = part.as_ref();
+        hasher.update((bytes.len() as u64).to_le_bytes());
+        hasher.update(bytes);
+    }
+    format!("{:x}", hasher.finalize())
+}
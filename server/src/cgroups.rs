@@ -0,0 +1,224 @@
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::path::{ Path, PathBuf };
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::sync::OnceLock;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::isolation::{ c_path, write_file };
+use crate::sandbox::Limits;
+
+// Each nargo run gets its own cgroup, so NARGO_MAX_PROCESSES caps the processes and threads of
+// that run alone with the pids controller. RLIMIT_NPROC would instead count every process of the
+// server's user, including other runs and the server itself, and is ignored for root.
+//
+// Run cgroups are made in a `nargo` cgroup inside NARGO_CGROUP_DIR, which defaults to the
+// server's own cgroup in the hierarchy with the pids controller (cgroup v1 or v2).
+static RUNS_DIR: OnceLock<Result<PathBuf, String>> = OnceLock::new();
+
+// Set when the server was left to start without per-run cgroups, so runs are not limited by them
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+// Function to check at startup that every run can be given its own cgroup, unless the process
+// limit is turned off
+pub fn check() -> Result<(), String> {
+    if Limits::get().max_processes == 0 {
+        return Ok(());
+    }
+    runs_dir().map(|_| ())
+}
+
+// Function to check whether the server must refuse to start without per-run cgroups, which is
+// when NARGO_CGROUP_DIR was set. The server's own cgroup is only used if it happens to be
+// writable.
+pub fn required() -> bool {
+    std::env::var_os("NARGO_CGROUP_DIR").is_some()
+}
+
+// Function to run nargo without per-run cgroups, after check found they cannot be made
+pub fn disable() {
+    DISABLED.store(true, Ordering::Relaxed);
+}
+
+fn runs_dir() -> Result<&'static Path, String> {
+    RUNS_DIR.get_or_init(set_up)
+        .as_deref()
+        .map_err(|e| e.clone())
+}
+
+fn set_up() -> Result<PathBuf, String> {
+    let base = match std::env::var("NARGO_CGROUP_DIR") {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_) => own_cgroup()?,
+    };
+    let unified = base.join("cgroup.controllers").exists();
+    if unified {
+        enable_controllers(&base)?;
+    }
+    let runs = base.join("nargo");
+    if let Err(e) = fs::create_dir(&runs) {
+        if e.kind() != io::ErrorKind::AlreadyExists {
+            return Err(format!("Failed to create cgroup {}: {}", runs.display(), e));
+        }
+    }
+    if unified {
+        enable_controllers(&runs)?;
+    }
+
+    // Remove cgroups left by an earlier run of the server
+    if let Ok(entries) = fs::read_dir(&runs) {
+        for entry in entries.filter_map(Result::ok) {
+            if entry.path().is_dir() {
+                let _ = fs::remove_dir(entry.path());
+            }
+        }
+    }
+    Ok(runs)
+}
+
+// Function to find the server's own cgroup in the hierarchy that has the pids controller
+fn own_cgroup() -> Result<PathBuf, String> {
+    let cgroups = fs
+        ::read_to_string("/proc/self/cgroup")
+        .map_err(|e| format!("Failed to read /proc/self/cgroup: {}", e))?;
+    let mut unified = None;
+    for line in cgroups.lines() {
+        let mut fields = line.splitn(3, ':');
+        let (Some(hierarchy), Some(controllers), Some(path)) = (
+            fields.next(),
+            fields.next(),
+            fields.next(),
+        ) else {
+            continue;
+        };
+        let path = path.trim_start_matches('/');
+        // cgroup v1 mounts the pids controller in a hierarchy of its own
+        if controllers.split(',').any(|controller| controller == "pids") {
+            return Ok(Path::new("/sys/fs/cgroup/pids").join(path));
+        }
+        if hierarchy == "0" && controllers.is_empty() {
+            unified = Some(Path::new("/sys/fs/cgroup").join(path));
+        }
+    }
+    unified
+        .filter(|dir| {
+            fs::read_to_string(dir.join("cgroup.controllers")).is_ok_and(|controllers| {
+                controllers.split_whitespace().any(|controller| controller == "pids")
+            })
+        })
+        .ok_or_else(|| "No cgroup with the pids controller was found".to_string())
+}
+
+// Function to let a cgroup v2 cgroup's children use the pids controller, and the memory
+// controller if there is one so that out-of-memory kills can be told apart. Only a cgroup
+// without processes of its own can do so, so the server moves into a child cgroup if it has to.
+fn enable_controllers(dir: &Path) -> Result<(), String> {
+    let subtree_control = dir.join("cgroup.subtree_control");
+    let enable = || {
+        let _ = fs::write(&subtree_control, "+memory");
+        fs::write(&subtree_control, "+pids")
+    };
+    if enable().is_ok() {
+        return Ok(());
+    }
+    let server = dir.join("server");
+    let moved = fs
+        ::create_dir_all(&server)
+        .and_then(|_| fs::write(server.join("cgroup.procs"), std::process::id().to_string()));
+    moved
+        .and_then(|_| enable())
+        .map_err(|e| format!("Failed to enable the pids controller in {}: {}", dir.display(), e))
+}
+
+// The cgroup a single nargo run is confined to. Dropping it kills anything still running in
+// it and removes it.
+pub struct RunCgroup {
+    dir: PathBuf,
+    procs: CString,
+}
+
+impl RunCgroup {
+    // Function to make a cgroup allowing at most max_processes processes and threads, or None
+    // when the limit is turned off or cgroups were disabled
+    pub fn create(max_processes: u64) -> io::Result<Option<RunCgroup>> {
+        if max_processes == 0 || DISABLED.load(Ordering::Relaxed) {
+            return Ok(None);
+        }
+        let runs = runs_dir().map_err(io::Error::other)?;
+        let dir = runs.join(Uuid::new_v4().to_string());
+        fs::create_dir(&dir)?;
+        let cgroup = RunCgroup { procs: c_path(&dir.join("cgroup.procs"))?, dir };
+        fs::write(cgroup.dir.join("pids.max"), max_processes.to_string())?;
+        Ok(Some(cgroup))
+    }
+
+    // Function to move the calling process into the cgroup. Runs between fork and exec, so it
+    // must only make async-signal-safe calls and must not allocate.
+    pub fn join(&self) -> io::Result<()> {
+        unsafe { write_file(&self.procs, c"0") }
+    }
+
+    // Function to check whether a process was refused because the cgroup was full
+    pub fn hit_process_limit(&self) -> bool {
+        event_count(&self.dir.join("pids.events"), "max") > 0
+    }
+
+    // Function to check whether the kernel killed a process in the cgroup for running out of
+    // memory, or None when the cgroup has no memory controller to tell
+    pub fn oom_killed(&self) -> Option<bool> {
+        let events = self.dir.join("memory.events");
+        events.exists().then(|| event_count(&events, "oom_kill") > 0)
+    }
+}
+
+// Function to kill everything in a cgroup, including processes that left nargo's process group
+fn kill_all(dir: &Path) {
+    if fs::write(dir.join("cgroup.kill"), "1").is_ok() {
+        return;
+    }
+    let procs = fs::read_to_string(dir.join("cgroup.procs")).unwrap_or_default();
+    for pid in procs.lines().filter_map(|pid| pid.trim().parse::<libc::pid_t>().ok()) {
+        unsafe {
+            libc::kill(pid, libc::SIGKILL);
+        }
+    }
+}
+
+impl Drop for RunCgroup {
+    fn drop(&mut self) {
+        // Killed processes take a moment to leave the cgroup, which cannot be removed until they
+        // have. Neither that nor writing to the cgroup must hold up the caller.
+        let dir = self.dir.clone();
+        let remove = move || {
+            kill_all(&dir);
+            for _ in 0..100 {
+                if fs::remove_dir(&dir).is_ok() {
+                    return;
+                }
+                std::thread::sleep(Duration::from_millis(20));
+            }
+            eprintln!("Failed to remove cgroup {}", dir.display());
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn_blocking(remove);
+            }
+            Err(_) => {
+                std::thread::spawn(remove);
+            }
+        }
+    }
+}
+
+// Function to read a counter from a cgroup events file, made of `name count` lines
+fn event_count(path: &Path, name: &str) -> u64 {
+    fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .find(|(key, _)| *key == name)
+        .and_then(|(_, count)| count.trim().parse().ok())
+        .unwrap_or(0)
+}
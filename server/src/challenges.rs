@@ -1,157 +1,521 @@
-use std::sync::Arc;
+use crate::curriculum::validate_prerequisites;
+use crate::lint::lint_test_cases;
+use crate::sources::HIDDEN_MODULE;
+use serde_derive::{ Deserialize, Serialize };
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{ HashMap, HashSet };
+use std::fmt;
+use std::fs;
+use std::hash::{ Hash, Hasher };
+use std::path::{ Path, PathBuf };
+use std::sync::{ Arc, Mutex, RwLock };
+
+// Files and directories every challenge package is made of
+const METADATA_FILE: &str = "challenge.toml";
+const DESCRIPTION_FILE: &str = "description.md";
+const TESTS_DIR: &str = "tests";
+const STARTER_FILE: &str = "starter.nr";
+const HELPERS_DIR: &str = "helpers";
+const SOLUTION_FILE: &str = "solution.nr";
+const SOLUTION_INPUTS_FILE: &str = "solution.toml";
+const VECTORS_FILE: &str = "vectors.toml";
+
+// Code the editor opens with when a package has no starter.nr
+const DEFAULT_STARTER_CODE: &str = "use dep::std;\n\nfn main() {\n}\n";
 
 pub struct Challenge {
     pub id: u32,
+    pub title: String,
+    pub track: String,
+    pub difficulty: Difficulty,
+    pub tags: Vec<String>,
+    pub estimated_minutes: Option<u32>,
+    pub prerequisites: Vec<u32>,
     pub description: String,
+    pub starter_code: String,
+    pub helpers: Vec<HelperModule>,
     pub test_cases: Vec<String>,
+    pub vectors: Vec<TestVector>,
+    pub solution: Option<Solution>,
+}
+
+// Prover inputs that a correct solution must either prove or refuse to prove
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TestVector {
+    pub name: String,
+    pub expect: Expectation,
+    #[serde(default)]
+    pub inputs: toml::value::Table,
+    // Value main must return, checked against the `return` entry of Verifier.toml
+    pub return_value: Option<toml::Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Expectation {
+    Success,
+    Failure,
+}
+
+// Structure of a package's vectors.toml
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct VectorsFile {
+    #[serde(default, rename = "vector")]
+    vectors: Vec<TestVector>,
+}
+
+// Read-only module shipped with a challenge, written next to the learner's main.nr
+#[derive(Serialize, Clone)]
+pub struct HelperModule {
+    pub name: String,
+    pub code: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    Beginner,
+    Intermediate,
+    Advanced,
+}
+
+// Reference solution, checked by the validate-challenges command
+pub struct Solution {
+    pub code: String,
+    pub prover_inputs: Option<toml::value::Table>,
+}
+
+// Structure of a package's challenge.toml
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ChallengeMetadata {
+    id: u32,
+    title: String,
+    track: String,
+    difficulty: Difficulty,
+    #[serde(default)]
+    tags: Vec<String>,
+    estimated_minutes: Option<u32>,
+    #[serde(default)]
+    prerequisites: Vec<u32>,
+}
+
+// Error pointing at the file (and field, when known) of a package that failed to load
+#[derive(Debug)]
+pub struct LoadError {
+    pub path: PathBuf,
+    pub field: Option<String>,
+    pub message: String,
+}
+
+impl LoadError {
+    fn new(path: &Path, message: impl Into<String>) -> Self {
+        LoadError { path: path.to_path_buf(), field: None, message: message.into() }
+    }
+
+    fn field(path: &Path, field: &str, message: impl Into<String>) -> Self {
+        LoadError { path: path.to_path_buf(), field: Some(field.into()), message: message.into() }
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.field {
+            Some(field) => write!(f, "{}: `{}`: {}", self.path.display(), field, self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+// Function to load and validate every challenge package in the given directory.
+// All problems are collected so authors can fix them in one go.
+pub fn load_challenges(dir: &Path) -> Result<Arc<Vec<Challenge>>, Vec<LoadError>> {
+    let entries = fs
+        ::read_dir(dir)
+        .map_err(|e| vec![LoadError::new(dir, format!("Failed to read directory: {}", e))])?;
+
+    // Every sub-directory is a package, loaded in name order so errors are reported stably
+    let mut package_dirs: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_dir())
+        .collect();
+    package_dirs.sort();
+
+    let mut challenges = vec![];
+    let mut errors = vec![];
+    let mut seen_ids: HashMap<u32, &Path> = HashMap::new();
+    for package_dir in &package_dirs {
+        let challenge = match load_package(package_dir) {
+            Ok(challenge) => challenge,
+            Err(mut package_errors) => {
+                errors.append(&mut package_errors);
+                continue;
+            }
+        };
+        // Challenge IDs are used in URLs and requests, so they must be unique
+        if let Some(first) = seen_ids.insert(challenge.id, package_dir) {
+            errors.push(
+                LoadError::field(
+                    &package_dir.join(METADATA_FILE),
+                    "id",
+                    format!("id {} is already used by {}", challenge.id, first.display())
+                )
+            );
+        }
+        challenges.push(challenge);
+    }
+
+    if challenges.is_empty() && errors.is_empty() {
+        errors.push(LoadError::new(dir, "No challenge packages found"));
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    // Prerequisites must point at loaded challenges and form a DAG
+    for (id, message) in validate_prerequisites(&challenges) {
+        let metadata_path = seen_ids[&id].join(METADATA_FILE);
+        errors.push(LoadError::field(&metadata_path, "prerequisites", message));
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    // Lint problems don't stop the challenges from loading, but authors should see them
+    for challenge in &challenges {
+        for warning in lint_test_cases(challenge) {
+            eprintln!("warning: {}: {}", seen_ids[&challenge.id].display(), warning);
+        }
+    }
+
+    challenges.sort_by_key(|c| c.id);
+    Ok(Arc::new(challenges))
+}
+
+// Function to load a single challenge package
+fn load_package(package_dir: &Path) -> Result<Challenge, Vec<LoadError>> {
+    let mut errors = vec![];
+
+    let metadata_path = package_dir.join(METADATA_FILE);
+    let metadata = read_file(&metadata_path).and_then(|content| {
+        toml::from_str::<ChallengeMetadata>(&content).map_err(|e|
+            LoadError::new(&metadata_path, e.to_string())
+        )
+    });
+    let metadata = match metadata {
+        Ok(metadata) => {
+            if metadata.title.trim().is_empty() {
+                errors.push(LoadError::field(&metadata_path, "title", "must not be empty"));
+            }
+            if metadata.track.trim().is_empty() {
+                errors.push(LoadError::field(&metadata_path, "track", "must not be empty"));
+            }
+            if metadata.tags.iter().any(|tag| tag.trim().is_empty()) {
+                let message = "must not contain empty tags";
+                errors.push(LoadError::field(&metadata_path, "tags", message));
+            }
+            Some(metadata)
+        }
+        Err(e) => {
+            errors.push(e);
+            None
+        }
+    };
+
+    let description_path = package_dir.join(DESCRIPTION_FILE);
+    let description = match read_file(&description_path) {
+        Ok(description) if description.trim().is_empty() => {
+            errors.push(LoadError::new(&description_path, "Description must not be empty"));
+            None
+        }
+        Ok(description) => Some(description),
+        Err(e) => {
+            errors.push(e);
+            None
+        }
+    };
+
+    let starter_path = package_dir.join(STARTER_FILE);
+    let starter_code = if starter_path.exists() {
+        match read_file(&starter_path) {
+            Ok(starter_code) => Some(starter_code),
+            Err(e) => {
+                errors.push(e);
+                None
+            }
+        }
+    } else {
+        Some(DEFAULT_STARTER_CODE.to_string())
+    };
+
+    let helpers_dir = package_dir.join(HELPERS_DIR);
+    let helpers = if helpers_dir.exists() {
+        match read_helpers(&helpers_dir) {
+            Ok(helpers) => Some(helpers),
+            Err(mut helper_errors) => {
+                errors.append(&mut helper_errors);
+                None
+            }
+        }
+    } else {
+        Some(vec![])
+    };
+
+    let test_cases = match read_test_cases(&package_dir.join(TESTS_DIR)) {
+        Ok(test_cases) => Some(test_cases),
+        Err(e) => {
+            errors.push(e);
+            None
+        }
+    };
+
+    let vectors = match read_vectors(&package_dir.join(VECTORS_FILE)) {
+        Ok(vectors) => Some(vectors),
+        Err(mut vector_errors) => {
+            errors.append(&mut vector_errors);
+            None
+        }
+    };
+
+    let solution = match read_solution(package_dir) {
+        Ok(solution) => solution,
+        Err(e) => {
+            errors.push(e);
+            None
+        }
+    };
+
+    match (metadata, description, starter_code, helpers, test_cases, vectors) {
+        (
+            Some(metadata),
+            Some(description),
+            Some(starter_code),
+            Some(helpers),
+            Some(test_cases),
+            Some(vectors),
+        ) if errors.is_empty() => {
+            Ok(Challenge {
+                id: metadata.id,
+                title: metadata.title,
+                track: metadata.track,
+                difficulty: metadata.difficulty,
+                tags: metadata.tags,
+                estimated_minutes: metadata.estimated_minutes,
+                prerequisites: metadata.prerequisites,
+                description,
+                starter_code,
+                helpers,
+                test_cases,
+                vectors,
+                solution,
+            })
+        }
+        _ => Err(errors),
+    }
+}
+
+// Function to read every .nr file in a package's tests directory, in file name order
+fn read_test_cases(tests_dir: &Path) -> Result<Vec<String>, LoadError> {
+    let entries = fs
+        ::read_dir(tests_dir)
+        .map_err(|e| LoadError::new(tests_dir, format!("Failed to read directory: {}", e)))?;
+
+    let mut test_paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "nr"))
+        .collect();
+    test_paths.sort();
+
+    if test_paths.is_empty() {
+        return Err(LoadError::new(tests_dir, "No .nr test case files found"));
+    }
+
+    test_paths
+        .iter()
+        .map(|path| read_file(path))
+        .collect()
+}
+
+// Function to read the optional list of test vectors
+fn read_vectors(vectors_path: &Path) -> Result<Vec<TestVector>, Vec<LoadError>> {
+    if !vectors_path.exists() {
+        return Ok(vec![]);
+    }
+    let content = read_file(vectors_path).map_err(|e| vec![e])?;
+    let vectors = toml
+        ::from_str::<VectorsFile>(&content)
+        .map_err(|e| vec![LoadError::new(vectors_path, e.to_string())])?.vectors;
+
+    let mut errors = vec![];
+    let mut names = HashSet::new();
+    for vector in &vectors {
+        if vector.name.trim().is_empty() {
+            errors.push(LoadError::field(vectors_path, "name", "must not be empty"));
+        } else if !names.insert(vector.name.as_str()) {
+            let message = format!("vector `{}` is declared more than once", vector.name);
+            errors.push(LoadError::field(vectors_path, "name", message));
+        }
+        if vector.expect == Expectation::Failure && vector.return_value.is_some() {
+            let message = format!("vector `{}` is expected to fail, so cannot return", vector.name);
+            errors.push(LoadError::field(vectors_path, "return_value", message));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(vectors)
+    } else {
+        Err(errors)
+    }
+}
+
+// Function to read the optional reference solution and the prover inputs used to prove it
+fn read_solution(package_dir: &Path) -> Result<Option<Solution>, LoadError> {
+    let solution_path = package_dir.join(SOLUTION_FILE);
+    if !solution_path.exists() {
+        return Ok(None);
+    }
+    let code = read_file(&solution_path)?;
+
+    let inputs_path = package_dir.join(SOLUTION_INPUTS_FILE);
+    let prover_inputs = if inputs_path.exists() {
+        let content = read_file(&inputs_path)?;
+        let inputs = toml
+            ::from_str::<toml::value::Table>(&content)
+            .map_err(|e| LoadError::new(&inputs_path, e.to_string()))?;
+        Some(inputs)
+    } else {
+        None
+    };
+
+    Ok(Some(Solution { code, prover_inputs }))
+}
+
+// Function to read helper modules. Each .nr file becomes a module named after the file,
+// so names must be valid Noir identifiers and must not clash with main.nr or the module the
+// hidden tests are written to.
+fn read_helpers(helpers_dir: &Path) -> Result<Vec<HelperModule>, Vec<LoadError>> {
+    let entries = fs::read_dir(helpers_dir).map_err(|e| {
+        vec![LoadError::new(helpers_dir, format!("Failed to read directory: {}", e))]
+    })?;
+
+    let mut helper_paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "nr"))
+        .collect();
+    helper_paths.sort();
+
+    let mut helpers = vec![];
+    let mut errors = vec![];
+    for path in helper_paths {
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        if !is_module_name(&name) {
+            errors.push(LoadError::new(&path, "File name must be a valid Noir module name"));
+            continue;
+        }
+        match read_file(&path) {
+            Ok(code) => helpers.push(HelperModule { name, code }),
+            Err(e) => errors.push(e),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(helpers)
+    } else {
+        Err(errors)
+    }
+}
+
+fn is_module_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    let starts_well = chars.next().is_some_and(|c| c.is_ascii_lowercase() || c == '_');
+    let reserved = name == "main" || name == HIDDEN_MODULE;
+    starts_well && chars.all(|c| c.is_ascii_alphanumeric() || c == '_') && !reserved
+}
+
+fn read_file(path: &Path) -> Result<String, LoadError> {
+    fs
+        ::read_to_string(path)
+        .map_err(|e| LoadError::new(path, format!("Failed to read file: {}", e)))
+}
+
+// Holds the currently loaded challenges and swaps in a new set when packages change.
+// Readers take a snapshot, so requests keep using the set they started with.
+pub struct ChallengeStore {
+    dir: PathBuf,
+    current: RwLock<Arc<Vec<Challenge>>>,
+    fingerprint: Mutex<u64>,
+}
+
+impl ChallengeStore {
+    // Function to create a store from the packages currently in the directory
+    pub fn load(dir: &Path) -> Result<Self, Vec<LoadError>> {
+        let fingerprint = fingerprint(dir);
+        let challenges = load_challenges(dir)?;
+        Ok(ChallengeStore {
+            dir: dir.to_path_buf(),
+            current: RwLock::new(challenges),
+            fingerprint: Mutex::new(fingerprint),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    // Function to get the challenge set a request should run against
+    pub fn snapshot(&self) -> Arc<Vec<Challenge>> {
+        Arc::clone(&self.current.read().unwrap())
+    }
+
+    // Function to reload every package. If any package is invalid, the previous set is kept.
+    pub fn reload(&self) -> Result<usize, Vec<LoadError>> {
+        *self.fingerprint.lock().unwrap() = fingerprint(&self.dir);
+        let challenges = load_challenges(&self.dir)?;
+        let count = challenges.len();
+        *self.current.write().unwrap() = challenges;
+        Ok(count)
+    }
+
+    // Function to reload only if a file in the challenge directory was added, removed or modified
+    // since the last load. Returns None when nothing changed.
+    pub fn reload_if_changed(&self) -> Option<Result<usize, Vec<LoadError>>> {
+        if *self.fingerprint.lock().unwrap() == fingerprint(&self.dir) {
+            return None;
+        }
+        Some(self.reload())
+    }
+}
+
+// Function to summarise the paths, sizes and modification times of every file under a directory
+fn fingerprint(dir: &Path) -> u64 {
+    let mut files = vec![];
+    collect_files(dir, &mut files);
+    files.sort();
+
+    let mut hasher = DefaultHasher::new();
+    for path in files {
+        let metadata = fs::metadata(&path).ok();
+        path.hash(&mut hasher);
+        metadata.as_ref().map(|m| m.len()).hash(&mut hasher);
+        metadata.and_then(|m| m.modified().ok()).hash(&mut hasher);
+    }
+    hasher.finish()
 }
 
-pub fn get_challenges() -> Arc<Vec<Challenge>> {
-    Arc::new(
-        vec![
-            Challenge {
-                id: 1,
-                description: "<p style=\"font-size: 20px; font-weight:bold;\">Assert</p>
-
-            <p>Welcome to Noir Playground! In this first challenge, we will write a program that asserts that two integers are not equal.</p>
-        
-            <p style=\"font-size: 18px; font-weight:bold;\">Example 1:</p>
-            <p>Input: x=1, y=2</p>
-            <ul>
-                <li>Program passes and proof is generated</li>
-                <li>x and y are not equal</li>
-            </ul>
-        
-            <p style=\"font-size: 18px; font-weight:bold;\">Example 2:</p>
-            <p>Input: x=5, y=5</p>
-            <ul>
-                <li>Program does not pass and proof is not generated</li>
-                <li>x and y are equal</li>
-            </ul>
-            
-            <p>On the right you will see some code already:</p>
-            <pre>
-                <code>
-                fn main() {}
-                </code>
-            </pre>
-        
-            <p>Please type your code into this main function.</p>
-            
-            <p>Optional: Click the `run` button to run any tests you have written. If you have not written any tests, the response will be empty. PS don’t worry if you don’t know about testing yet - we will learn more later.</p>
-            
-            <p>The prover inputs are the values you would like to input to generate a proof of program execution. In the first example, the prover inputs are x:1 and y:2.</p>
-        
-            <p>When you are happy with your code and prover inputs, click the `Submit` button to check that your code works and to run the prover and verifier. See the results in the Response box.</p>
-        
-            <p style=\"font-size: 18px; font-weight:bold;\">Hint:</p>
-            <p>If you're stuck, check out the Intro to Noir Syntax</p>".into(),
-                test_cases: vec![
-                    "#[test]
-                fn test_main() {
-                    main(1,2);
-                    main(0,5);
-                }".into()
-                ],
-            },
-            Challenge {
-                id: 2,
-                description: "<p style=\"font-size: 20px; font-weight:bold;\">Functions and Variables</p>
-            <p>This challenge is to create two functions: <code>main</code> and <code>add</code> in Noir lang. </p>
-        
-            <ul>
-                <li><b>add function:</b> It should take two numbers as inputs, add them together, and then return the result.</li>
-                <li><b>main function:</b> This should take two numbers as inputs, call the <code>add</code> function with these inputs, and check if the returned result is the same as the sum of the inputs.
-            </ul>
-            <p>Your task is to implement the two functions. Here's an example of what your inputs might look like:</p>
-        
-            <p style=\"font-size: 18px; font-weight:bold;\">Hint:</p>
-            <p>Use <code>let</code> to declare variables.</p>
-            <p>When you are happy with your code, click `Check` to get the prover inputs. Then fill them out and click the `Submit` button. Enjoy coding!</p>".into(),
-                test_cases: vec![
-                    "fn test_main() {
-                    main(1, 2);
-                    main(0,1);
-                    add(1,2);
-                }
-                ".into()
-                ],
-            },
-            Challenge {
-                id: 3,
-                description: "<p style=\"font-size: 20px; font-weight:bold;\">A Merkle Tree</p>
-            <p>
-               is a data structure used to verify the integrity of data. Every piece of information, 
-               or a leaf, is hashed. These hashes are then paired, hashed, paired again, and hashed again 
-               until you get a single hash, known as the root. This process forms a tree-like diagram of 
-               hashes, hence the name, Merkle Tree.
-            </p>
-        
-            <p>
-                In this task, we are going to take a famous example of Merkle Trees: ICONOMI’s proof of solvency.
-                ICONOMI created a merkle tree of all of their commitments, i.e user accounts and owed loans,
-                and allowed users to check that their account was included in the Merkle tree without giving them
-                access to other user’s sensitive data. You can read more about that 
-                <a href=\"https://medium.com/iconominet/proof-of-solvency-technical-overview-d1d0e8a8a0b8\">here</a>.
-            </p>
-        
-            <p style=\"font-size: 18px; font-weight:bold;\">Task</p>
-            <p>
-                You will write a Noir program that allows the user to check that their account is included in a given
-                Merkle tree. These are the inputs that will be used to test and prove the function so please take them 
-                in this order:
-            </p>
-            
-            <ul>
-                <li><code>root</code>: The root of the Merkle tree.</li>
-                <li><code>account_id</code>: The unique ID of the account.</li>
-                <li><code>index</code>: The index path of the account in the tree.</li>
-                <li><code>hash_path_1</code>: First part of the hash path.</li>
-                <li><code>hash_path_2</code>: Second part of the hash path.</li>
-                <li><code>commitment</code>: The commitment of this account in the tree.</li>
-            </ul>
-        
-            <p>
-                You should compute a Merkle root using this information and check that it matches the existing 
-                Merkle root given to the user by the company.
-            </p>
-        
-            <p style=\"font-size: 18px; font-weight:bold;\">Hint</p>
-            <p>
-                You can write tests as helper functions, for example generating a tree and then testing that your program
-                generates the same hashes.
-            </p>
-        
-            <p style=\"font-size: 18px; font-weight:bold;\">Helpful Functions</p>
-        
-            <pre>
-                <code>
-                    std::hash::pedersen()
-        
-                    std::merkle::compute_merkle_root
-                </code>
-            </pre>
-        
-            <p>Good luck!</p>".into(),
-                test_cases: vec![
-                    "#[test]
-                fn test_main() {
-                    let root = 0x29fd5ee89e33f559a7b32ac39f57400aa5a6c77492e28c088f9eb511b0c73e78; // Root from built Merkle Tree
-                    let account_id = 1;
-                    let index = 0;
-                
-                    // Hash path should include sibling hashes from commitment to root.
-                    let hash_path_1 = 0x2d961d9814298c04a4639a56c5c95030d704340ab6d13c135a326da5e515559d; // Sibling of commitment1 -> commitment2 (sibling at level 1)
-                    let hash_path_2 = 0x1501e80783ee5c988327f46f5fcdce388cb97aa7e959ad345c1e2cbaa0b42b83; // Sibling of left_branch -> right_branch (sibling at level 2)
-                
-                
-                    let id_commitment = std::hash::pedersen([account_id])[0];
-                    let computed_root = main(root, account_id, index, hash_path_1, hash_path_2, id_commitment); 
-                
-                    assert(root == computed_root);
-                }".into()
-                ],
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    if let Ok(entries) = fs::read_dir(dir) {
+        for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+            if path.is_dir() {
+                collect_files(&path, files);
+            } else {
+                files.push(path);
             }
-        ]
-    )
+        }
+    }
 }
@@ -0,0 +1,117 @@
+use crate::challenges::Challenge;
+use serde_derive::Serialize;
+use std::collections::{ BTreeSet, HashMap, HashSet };
+
+// A named track and its challenges, in an order that respects prerequisites
+#[derive(Serialize)]
+pub struct Track {
+    pub name: String,
+    pub challenges: Vec<TrackChallenge>,
+}
+
+#[derive(Serialize)]
+pub struct TrackChallenge {
+    pub id: u32,
+    pub title: String,
+    pub prerequisites: Vec<u32>,
+}
+
+// Function to check that every prerequisite exists and that the prerequisite graph is acyclic.
+// Returns the ID of the offending challenge along with a description of each problem.
+pub fn validate_prerequisites(challenges: &[Challenge]) -> Vec<(u32, String)> {
+    let ids: HashSet<u32> = challenges
+        .iter()
+        .map(|c| c.id)
+        .collect();
+    let mut errors = vec![];
+
+    for challenge in challenges {
+        for (i, prerequisite) in challenge.prerequisites.iter().enumerate() {
+            let message = if challenge.prerequisites[..i].contains(prerequisite) {
+                format!("prerequisite {} is listed twice", prerequisite)
+            } else if *prerequisite == challenge.id {
+                "a challenge cannot be its own prerequisite".to_string()
+            } else if !ids.contains(prerequisite) {
+                format!("unknown prerequisite challenge {}", prerequisite)
+            } else {
+                continue;
+            };
+            errors.push((challenge.id, message));
+        }
+    }
+    if !errors.is_empty() {
+        return errors;
+    }
+
+    let order = topological_order(challenges);
+    if order.len() < challenges.len() {
+        let ordered: HashSet<u32> = order.into_iter().collect();
+        let mut remaining: Vec<u32> = ids.difference(&ordered).copied().collect();
+        remaining.sort();
+        let listed = remaining
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        for id in remaining {
+            errors.push((id, format!("prerequisites form a cycle among challenges {}", listed)));
+        }
+    }
+    errors
+}
+
+// Function to order challenge IDs so every challenge comes after its prerequisites.
+// Ties are broken by the lowest ID so the order is stable. Challenges on a cycle are left out.
+pub fn topological_order(challenges: &[Challenge]) -> Vec<u32> {
+    let mut pending: HashMap<u32, usize> = challenges
+        .iter()
+        .map(|c| (c.id, c.prerequisites.len()))
+        .collect();
+    let mut ready: BTreeSet<u32> = pending
+        .iter()
+        .filter(|(_, count)| **count == 0)
+        .map(|(id, _)| *id)
+        .collect();
+    let mut order = vec![];
+
+    while let Some(id) = ready.pop_first() {
+        order.push(id);
+        for challenge in challenges.iter().filter(|c| c.prerequisites.contains(&id)) {
+            if let Some(count) = pending.get_mut(&challenge.id) {
+                *count -= 1;
+                if *count == 0 {
+                    ready.insert(challenge.id);
+                }
+            }
+        }
+    }
+    order
+}
+
+// Function to group challenges into tracks. Tracks are listed in the order their first
+// challenge appears in the curriculum, and challenges within a track follow prerequisites.
+pub fn build_tracks(challenges: &[Challenge]) -> Vec<Track> {
+    let by_id: HashMap<u32, &Challenge> = challenges
+        .iter()
+        .map(|c| (c.id, c))
+        .collect();
+    let mut tracks: Vec<Track> = vec![];
+
+    for id in topological_order(challenges) {
+        let challenge = by_id[&id];
+        let entry = TrackChallenge {
+            id: challenge.id,
+            title: challenge.title.clone(),
+            prerequisites: challenge.prerequisites.clone(),
+        };
+        match tracks.iter_mut().find(|t| t.name == challenge.track) {
+            Some(track) => track.challenges.push(entry),
+            None =>
+                tracks.push(Track {
+                    name: challenge.track.clone(),
+                    challenges: vec![entry],
+                }),
+        }
+    }
+    tracks
+}
@@ -0,0 +1,249 @@
+use serde_derive::{ Deserialize, Serialize };
+
+// An error or warning nargo reported about the code, parsed from output like:
+//
+// error: cannot find `y` in this scope
+//   ┌─ src/main.nr:2:12
+//   │
+// 2 │     assert(x == y);
+//   │                 - not found in this scope
+//   │
+//   = Call stack: ...
+//
+// Lines and columns start at 1. The location is where nargo points at first, and its length
+// comes from the label underlining it; diagnostics about the whole program have no location.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub file: Option<String>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub length: Option<usize>,
+    // Every span nargo underlined, including the one at the location
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+// A span nargo underlined, with what it said about it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Label {
+    pub message: String,
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub length: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Bug,
+}
+
+// Function to parse the diagnostics out of nargo's output, which must already have its
+// terminal colour codes removed. Anything else nargo printed is skipped.
+pub fn parse(output: &str) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = vec![];
+    // The file and line of the last source line shown, and where its code starts
+    let mut file = String::new();
+    let mut source: Option<(usize, usize)> = None;
+    // Labels on the last underline whose message is written on a line below it
+    let mut unlabelled: Vec<usize> = vec![];
+    let mut in_note = false;
+
+    for line in output.lines() {
+        if let Some(diagnostic) = parse_header(line) {
+            diagnostics.push(diagnostic);
+            source = None;
+            unlabelled.clear();
+            in_note = false;
+            continue;
+        }
+        let Some(diagnostic) = diagnostics.last_mut() else {
+            continue;
+        };
+        let trimmed = line.trim_start();
+
+        if let Some((path, line, column)) = location(line) {
+            file = path;
+            if diagnostic.file.is_none() {
+                diagnostic.file = Some(file.clone());
+                diagnostic.line = Some(line);
+                diagnostic.column = Some(column);
+            }
+            in_note = false;
+        } else if let Some(note) = trimmed.strip_prefix("= ") {
+            diagnostic.notes.push(note.trim_end().to_string());
+            in_note = true;
+        } else if let Some((number, start)) = parse_source_line(line) {
+            source = Some((number, start));
+            unlabelled.clear();
+            in_note = false;
+        } else if let (Some((number, start)), Some(_)) = (source, gutter(line)) {
+            // Underlines line up with the code above them
+            let code = line.chars().skip(start).collect::<String>();
+            if let Some(spans) = parse_underline(&code) {
+                unlabelled.clear();
+                for (column, length, message) in spans {
+                    if message.is_empty() {
+                        unlabelled.push(diagnostic.labels.len());
+                    }
+                    diagnostic.labels.push(Label {
+                        message,
+                        file: file.clone(),
+                        line: number,
+                        column: column + 1,
+                        length,
+                    });
+                }
+            } else {
+                // A label's message written below the underline, lined up with its start
+                let text = code.trim_start();
+                let column = code.chars().count() - text.chars().count() + 1;
+                let found = unlabelled
+                    .iter()
+                    .position(|&i| diagnostic.labels[i].column == column);
+                if let Some(found) = found {
+                    if !text.is_empty() && !text.starts_with('│') && !text.starts_with('|') {
+                        diagnostic.labels[unlabelled.remove(found)].message = text.to_string();
+                    }
+                }
+            }
+            in_note = false;
+        } else if in_note && line.starts_with(' ') && !trimmed.is_empty() {
+            // Notes such as call stacks carry on over indented lines
+            let note = diagnostic.notes.last_mut().unwrap();
+            note.push('\n');
+            note.push_str(trimmed.trim_end());
+        } else {
+            in_note = false;
+        }
+    }
+
+    for diagnostic in &mut diagnostics {
+        let at_location = diagnostic.labels.iter().find(|label| {
+            Some(&label.file) == diagnostic.file.as_ref() &&
+                Some(label.line) == diagnostic.line &&
+                Some(label.column) == diagnostic.column
+        });
+        diagnostic.length = at_location.map(|label| label.length);
+    }
+    diagnostics
+}
+
+// Function to get the severity of the diagnostic a line starts, if it starts one
+pub fn header_severity(line: &str) -> Option<Severity> {
+    parse_header(line).map(|diagnostic| diagnostic.severity)
+}
+
+// Function to parse a line like `┌─ src/main.nr:2:17` that gives the file, line and column
+// nargo is pointing at
+pub fn location(line: &str) -> Option<(String, usize, usize)> {
+    let trimmed = line.trim_start();
+    let location = trimmed.strip_prefix("┌─").or(trimmed.strip_prefix("-->"))?;
+    parse_location(location.trim())
+}
+
+// Function to get the line number of a line of source code nargo quoted
+pub fn source_line_number(line: &str) -> Option<usize> {
+    parse_source_line(line).map(|(number, _)| number)
+}
+
+// Function to find the character just after the `│ ` that separates line numbers from code,
+// if the line has one
+pub fn gutter(line: &str) -> Option<usize> {
+    let bar = line.chars().position(|c| c == '│' || c == '|')?;
+    if !line.chars().take(bar).all(|c| c == ' ' || c.is_ascii_digit()) {
+        return None;
+    }
+    Some(bar + 2)
+}
+
+// Function to parse a line like `error: message` that starts a diagnostic
+fn parse_header(line: &str) -> Option<Diagnostic> {
+    let (severity, rest) = [
+        ("error", Severity::Error),
+        ("warning", Severity::Warning),
+        ("bug", Severity::Bug),
+    ]
+        .into_iter()
+        .find_map(|(prefix, severity)| line.strip_prefix(prefix).map(|rest| (severity, rest)))?;
+    // Some diagnostics carry a code, as in `error[E0001]: message`
+    let rest = match rest.strip_prefix('[') {
+        Some(rest) => rest.split_once(']')?.1,
+        None => rest,
+    };
+    let message = rest.strip_prefix(':')?.trim();
+    Some(Diagnostic {
+        severity,
+        message: message.to_string(),
+        file: None,
+        line: None,
+        column: None,
+        length: None,
+        labels: vec![],
+        notes: vec![],
+    })
+}
+
+// Function to parse `path:line:column`, making paths inside the project relative to it so the
+// server's directories are not given away
+fn parse_location(location: &str) -> Option<(String, usize, usize)> {
+    let mut parts = location.rsplitn(3, ':');
+    let column = parts.next()?.trim().parse().ok()?;
+    let line = parts.next()?.trim().parse().ok()?;
+    let path = parts.next()?;
+    let path = match path.rfind("/src/") {
+        Some(i) if path.starts_with('/') => &path[i + 1..],
+        _ => path,
+    };
+    Some((path.to_string(), line, column))
+}
+
+// Function to parse a line of source code shown as `12 │ code`, returning the line number and
+// the character the code starts at
+fn parse_source_line(line: &str) -> Option<(usize, usize)> {
+    let number = line.trim_start();
+    let digits = number.chars().take_while(char::is_ascii_digit).count();
+    let rest = number[digits..].trim_start();
+    if digits == 0 || !(rest.starts_with('│') || rest.starts_with('|')) {
+        return None;
+    }
+    Some((number[..digits].parse().ok()?, gutter(line)?))
+}
+
+// Function to parse a line underlining spans with `^^^` or `---`, each optionally followed by
+// a message, returning each span's 0-based column, length and message. Only the last span on
+// a line can have its message beside it; the others are written on lines below.
+fn parse_underline(code: &str) -> Option<Vec<(usize, usize, String)>> {
+    let chars: Vec<char> = code.chars().collect();
+    let mut spans: Vec<(usize, usize, String)> = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            ' ' => {
+                i += 1;
+            }
+            '^' | '-' => {
+                let marker = chars[i];
+                let start = i;
+                while i < chars.len() && chars[i] == marker {
+                    i += 1;
+                }
+                spans.push((start, i - start, String::new()));
+            }
+            _ => {
+                let message = chars[i..].iter().collect::<String>();
+                spans.last_mut()?.2 = message.trim_end().to_string();
+                break;
+            }
+        }
+    }
+    if spans.is_empty() {
+        return None;
+    }
+    Some(spans)
+}
@@ -0,0 +1,142 @@
+use crate::diagnostics::Diagnostic;
+use crate::queue::QueueFull;
+use crate::sandbox::{ LimitExceeded, LimitKind };
+use crate::workspace::WorkspaceError;
+use serde_derive::Serialize;
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+use warp::http::StatusCode;
+use warp::reject::Reject;
+
+// Everything a request can fail with. Each kind of error has its own HTTP status and a
+// machine-readable code, and is returned as an ErrorBody.
+#[derive(Debug)]
+pub enum ApiError {
+    // The learner's code could not be compiled or run, with what nargo reported about it
+    Code(String, Vec<Diagnostic>),
+    // The request is malformed or asks for something that cannot be done
+    BadRequest(String),
+    // The request refers to a challenge that does not exist
+    ChallengeNotFound(u32),
+    // Nothing is served at the path, or the job does not exist
+    NotFound(String),
+    // nargo was stopped for exceeding a resource limit
+    Limit(LimitExceeded),
+    // Too many jobs are waiting to run
+    Overloaded(QueueFull),
+    // Project directories use all the disk they are allowed until the janitor frees some
+    DiskFull,
+    // Something went wrong on the server, such as failing to write a file
+    Internal(String),
+}
+
+// JSON returned for every error
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct ErrorBody {
+    pub code: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl ApiError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::Code(..) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::ChallengeNotFound(_) | ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Limit(e) if e.kind == LimitKind::WallClock => StatusCode::REQUEST_TIMEOUT,
+            ApiError::Limit(e) if e.kind == LimitKind::Killed => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::Limit(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::Overloaded(_) => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::DiskFull => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    // Function to get the code clients can match on, which never changes for a kind of error
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::Code(..) => "code_error",
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::ChallengeNotFound(_) => "challenge_not_found",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Limit(e) =>
+                match e.kind {
+                    LimitKind::CpuTime => "cpu_time_limit",
+                    LimitKind::Memory => "memory_limit",
+                    LimitKind::FileSize => "file_size_limit",
+                    LimitKind::WallClock => "time_limit",
+                    LimitKind::Processes => "process_limit",
+                    LimitKind::Killed => "killed",
+                }
+            ApiError::Overloaded(_) => "overloaded",
+            ApiError::DiskFull => "disk_full",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+
+    pub fn body(&self) -> ErrorBody {
+        let diagnostics = match self {
+            ApiError::Code(_, diagnostics) => diagnostics.clone(),
+            _ => vec![],
+        };
+        ErrorBody {
+            code: self.code(),
+            message: self.to_string(),
+            diagnostics,
+        }
+    }
+
+    // Function to get how long an overloaded caller should wait before trying again
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            ApiError::Overloaded(e) => Some(e.retry_after),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ApiError::Code(message, _) => write!(f, "{}", message),
+            ApiError::BadRequest(message) => write!(f, "{}", message),
+            ApiError::ChallengeNotFound(id) => write!(f, "No challenge found with ID {}", id),
+            ApiError::NotFound(message) => write!(f, "{}", message),
+            ApiError::Limit(e) => write!(f, "{}", e),
+            ApiError::Overloaded(e) => write!(f, "{}", e),
+            ApiError::DiskFull => {
+                write!(f, "Too little disk space is left to run nargo, try again later")
+            }
+            ApiError::Internal(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for ApiError {}
+impl Reject for ApiError {}
+
+impl From<LimitExceeded> for ApiError {
+    fn from(e: LimitExceeded) -> Self {
+        ApiError::Limit(e)
+    }
+}
+
+impl From<QueueFull> for ApiError {
+    fn from(e: QueueFull) -> Self {
+        ApiError::Overloaded(e)
+    }
+}
+
+impl From<WorkspaceError> for ApiError {
+    fn from(e: WorkspaceError) -> Self {
+        match e {
+            WorkspaceError::DiskFull => ApiError::DiskFull,
+            WorkspaceError::Io(e) => {
+                ApiError::Internal(format!("Failed to create project directory: {}", e))
+            }
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use crate::error::ApiError;
+use serde_json::Value as Json;
+use std::collections::BTreeMap;
+use toml::value::{ Table, Value };
+
+// Prover inputs as sent in a request, kept in order so the same inputs always make the same
+// Prover.toml
+pub type ProverInputs = BTreeMap<String, Json>;
+
+// Function to write prover inputs sent as JSON as the contents of a Prover.toml. Numbers become
+// integers and strings stay strings, so fields can be given either way, including as hex strings
+// like "0x1f". Arrays become arrays and objects become tables, for array and struct parameters.
+pub fn prover_toml(inputs: &ProverInputs) -> Result<String, ApiError> {
+    let table = to_table(inputs.iter(), "")?;
+    table_toml(&table).map_err(|e| {
+        ApiError::BadRequest(format!("Prover inputs cannot be written as TOML: {}", e))
+    })
+}
+
+// Function to write a table of prover inputs, such as a test vector's, as the contents of a
+// Prover.toml. The table is written as a value, so toml puts struct inputs after the plain ones
+// however their names sort.
+pub fn table_toml(table: &Table) -> Result<String, toml::ser::Error> {
+    toml::to_string(&Value::Table(table.clone()))
+}
+
+// Function to check a test name filter before it is passed to nargo test, which only takes a
+// Noir path like `hidden_test` or `tests::sums`, so the learner cannot slip in a flag
+pub fn test_filter(test_name: Option<&str>) -> Result<Option<&str>, ApiError> {
+    let Some(name) = test_name else {
+        return Ok(None);
+    };
+    let is_path = name.split("::").all(|segment| {
+        let mut chars = segment.chars();
+        chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_') &&
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+    });
+    if !is_path {
+        return Err(ApiError::BadRequest(format!("Test name `{}` is not a Noir path", name)));
+    }
+    Ok(Some(name))
+}
+
+fn to_table<'a>(
+    entries: impl Iterator<Item = (&'a String, &'a Json)>,
+    parent: &str
+) -> Result<Table, ApiError> {
+    let mut table = Table::new();
+    for (name, value) in entries {
+        let path = if parent.is_empty() { name.clone() } else { format!("{}.{}", parent, name) };
+        let is_identifier = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if name.is_empty() || !is_identifier {
+            return Err(invalid(&path, "is not a valid parameter or field name"));
+        }
+        table.insert(name.clone(), to_toml(value, &path)?);
+    }
+    Ok(table)
+}
+
+// Function to convert a single JSON value, naming it by its path in errors
+fn to_toml(value: &Json, path: &str) -> Result<Value, ApiError> {
+    match value {
+        Json::Null => Err(invalid(path, "is null")),
+        Json::Bool(value) => Ok(Value::Boolean(*value)),
+        Json::String(value) => Ok(Value::String(value.clone())),
+        Json::Number(number) => {
+            if let Some(value) = number.as_i64() {
+                Ok(Value::Integer(value))
+            } else if let Some(value) = number.as_u64() {
+                // Too large for a TOML integer, but nargo reads fields from decimal strings
+                Ok(Value::String(value.to_string()))
+            } else {
+                Err(
+                    invalid(
+                        path,
+                        "is not a whole number; send fractions or numbers too large for JSON as \
+                         strings"
+                    )
+                )
+            }
+        }
+        Json::Array(elements) => {
+            let mut values = elements
+                .iter()
+                .enumerate()
+                .map(|(i, element)| to_toml(element, &format!("{}[{}]", path, i)))
+                .collect::<Result<Vec<_>, _>>()?;
+            // Fields can be given as numbers or strings, but the elements of a TOML array must
+            // all have the same type
+            let has_strings = values.iter().any(|value| value.is_str());
+            if has_strings && values.iter().any(|value| value.is_integer()) {
+                for value in &mut values {
+                    if let Value::Integer(integer) = value {
+                        *value = Value::String(integer.to_string());
+                    }
+                }
+            }
+            if let Some(first) = values.first() {
+                if values.iter().any(|value| value.type_str() != first.type_str()) {
+                    return Err(invalid(path, "mixes elements of different types"));
+                }
+            }
+            Ok(Value::Array(values))
+        }
+        Json::Object(fields) => Ok(Value::Table(to_table(fields.iter(), path)?)),
+    }
+}
+
+fn invalid(path: &str, problem: &str) -> ApiError {
+    ApiError::BadRequest(format!("Prover input `{}` {}", path, problem))
+}
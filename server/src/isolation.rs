@@ -0,0 +1,557 @@
+use std::ffi::{ CStr, CString };
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{ Path, PathBuf };
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::sync::OnceLock;
+
+use crate::sandbox::{ self, Limits };
+
+// Isolation applied to every nargo process on top of the resource limits. Each process gets its
+// own user, mount, PID, network, IPC and UTS namespaces. Inside them the whole filesystem is
+// read-only except the project directory, private directories are mounted over anything that
+// should not be readable, only the processes of the same run can be seen or signalled, there are
+// no network interfaces apart from a loopback that is down, every capability is dropped and a
+// seccomp filter refuses syscalls nargo has no use for.
+#[derive(Debug)]
+pub struct Isolation {
+    // NARGO_ISOLATION: `on`, `off`, or unset to isolate nargo if the host allows it
+    pub enabled: bool,
+    // Whether the server must refuse to start if nargo cannot be isolated, when NARGO_ISOLATION
+    // is `on`
+    pub required: bool,
+    // Directories replaced with an empty tmpfs: /tmp, the server's working directory,
+    // CHALLENGES_DIR, WORKSPACE_DIR, ARTIFACT_CACHE_DIR and anything in NARGO_HIDDEN_PATHS
+    // (separated by `:`). The project directory of the run is mounted back.
+    pub hidden_paths: Vec<PathBuf>,
+}
+
+static ISOLATION: OnceLock<Isolation> = OnceLock::new();
+
+// Set when the server was left to start without isolation, so nargo runs without it
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+impl Isolation {
+    // Function to get the isolation configured for this server
+    pub fn get() -> &'static Isolation {
+        ISOLATION.get_or_init(Isolation::from_env)
+    }
+
+    fn from_env() -> Self {
+        let (enabled, required) = match std::env::var("NARGO_ISOLATION").as_deref() {
+            Ok("on") => (true, true),
+            Err(_) => (true, false),
+            Ok("off") => (false, false),
+            Ok(_) => panic!("NARGO_ISOLATION must be `on` or `off`."),
+        };
+
+        let mut paths = vec![PathBuf::from("/tmp"), PathBuf::from(".")];
+        paths.push(
+            PathBuf::from(
+                std::env::var("CHALLENGES_DIR").unwrap_or_else(|_| "challenges".to_string())
+            )
+        );
+        // Other runs' projects and compiled circuits, which default to somewhere under the
+        // working directory
+        for name in ["WORKSPACE_DIR", "ARTIFACT_CACHE_DIR"] {
+            if let Ok(dir) = std::env::var(name) {
+                paths.push(PathBuf::from(dir));
+            }
+        }
+        if let Ok(extra) = std::env::var("NARGO_HIDDEN_PATHS") {
+            paths.extend(
+                extra
+                    .split(':')
+                    .filter(|p| !p.is_empty())
+                    .map(PathBuf::from)
+            );
+        }
+
+        // Only the outermost directories are mounted over, as a directory inside one of them
+        // is already hidden
+        let mut paths: Vec<PathBuf> = paths
+            .iter()
+            .filter_map(|p| p.canonicalize().ok())
+            .collect();
+        paths.sort();
+        paths.dedup();
+        let hidden_paths = paths
+            .iter()
+            .filter(|p| !paths.iter().any(|other| other != *p && p.starts_with(other)))
+            .cloned()
+            .collect();
+
+        Isolation { enabled, required, hidden_paths }
+    }
+}
+
+// Function to check at startup that nargo can be isolated on this host, by running `true`
+// the same way nargo will be run
+pub async fn check() -> Result<(), String> {
+    if !Isolation::get().enabled {
+        return Ok(());
+    }
+    let mut cmd_obj = tokio::process::Command::new("true");
+    cmd_obj.current_dir(std::env::current_dir().map_err(|e| e.to_string())?);
+    match sandbox::run_limited(cmd_obj, None).await {
+        Ok(output) if output.status.success() => Ok(()),
+        Ok(output) => Err(String::from_utf8_lossy(&output.stderr).trim().to_string()),
+        Err(sandbox::RunError::Io(e)) => Err(e.to_string()),
+        Err(sandbox::RunError::Limit(e)) => Err(e.to_string()),
+    }
+}
+
+// Function to run nargo without isolation, after check found the host does not allow it
+pub fn disable() {
+    DISABLED.store(true, Ordering::Relaxed);
+}
+
+// Everything needed to isolate one process, worked out before forking so that the child only
+// has to make syscalls
+pub struct Prepared {
+    uid_map: CString,
+    gid_map: CString,
+    // Mount points with the flags they must keep when remounted read-only
+    mounts: Vec<(CString, libc::c_ulong)>,
+    hidden_paths: Vec<CString>,
+    tmpfs_options: CString,
+    work_dir: CString,
+    // Directories to create inside a tmpfs so that the project directory can be mounted back
+    work_dir_parents: Vec<CString>,
+    filter: Vec<libc::sock_filter>,
+}
+
+// Function to prepare the isolation of a process that works in work_dir, or None when
+// isolation is turned off
+pub fn prepare(work_dir: &Path) -> io::Result<Option<Prepared>> {
+    let isolation = Isolation::get();
+    if !isolation.enabled || DISABLED.load(Ordering::Relaxed) {
+        return Ok(None);
+    }
+    let work_dir = work_dir.canonicalize()?;
+
+    let uid = unsafe { libc::geteuid() };
+    let gid = unsafe { libc::getegid() };
+    let mountinfo = std::fs::read_to_string("/proc/self/mountinfo")?;
+
+    let mut work_dir_parents = vec![];
+    if let Some(hidden) = isolation.hidden_paths.iter().find(|h| work_dir.starts_with(h)) {
+        let mut dir = hidden.clone();
+        for component in work_dir.strip_prefix(hidden).unwrap().components() {
+            dir.push(component);
+            work_dir_parents.push(c_path(&dir)?);
+        }
+    }
+
+    let max_file_bytes = Limits::get().max_file_bytes;
+    let tmpfs_options = if max_file_bytes == 0 {
+        "mode=1777".to_string()
+    } else {
+        format!("mode=1777,size={}", max_file_bytes)
+    };
+
+    Ok(
+        Some(Prepared {
+            uid_map: CString::new(format!("{} {} 1", uid, uid)).unwrap(),
+            gid_map: CString::new(format!("{} {} 1", gid, gid)).unwrap(),
+            mounts: parse_mountinfo(&mountinfo)?,
+            hidden_paths: isolation.hidden_paths
+                .iter()
+                .map(|p| c_path(p))
+                .collect::<Result<_, _>>()?,
+            tmpfs_options: CString::new(tmpfs_options).unwrap(),
+            work_dir: c_path(&work_dir)?,
+            work_dir_parents,
+            filter: seccomp_filter(),
+        })
+    )
+}
+
+impl Prepared {
+    // Function to isolate the calling process. Runs between fork and exec, so it must only make
+    // async-signal-safe calls and must not allocate.
+    pub fn apply(&self) -> io::Result<()> {
+        unsafe {
+            cvt(
+                libc::unshare(
+                    libc::CLONE_NEWUSER |
+                        libc::CLONE_NEWNS |
+                        libc::CLONE_NEWPID |
+                        libc::CLONE_NEWNET |
+                        libc::CLONE_NEWIPC |
+                        libc::CLONE_NEWUTS
+                )
+            )?;
+            // Keep the same user and group inside the namespace, so files in the project
+            // directory are owned by the server
+            write_file(c"/proc/self/setgroups", c"deny")?;
+            write_file(c"/proc/self/uid_map", &self.uid_map)?;
+            write_file(c"/proc/self/gid_map", &self.gid_map)?;
+
+            // Stop mount changes propagating back to the host
+            cvt(mount(None, c"/", None, libc::MS_REC | libc::MS_PRIVATE, None))?;
+
+            // Give the project directory its own writable mount and keep hold of it, as it may
+            // be about to be hidden
+            let work_dir = &self.work_dir;
+            cvt(mount(Some(work_dir), work_dir, None, libc::MS_BIND, None))?;
+            let work_fd = libc::open(
+                work_dir.as_ptr(),
+                libc::O_PATH | libc::O_DIRECTORY | libc::O_CLOEXEC
+            );
+            if work_fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            for (point, flags) in &self.mounts {
+                let flags = libc::MS_REMOUNT | libc::MS_BIND | libc::MS_RDONLY | flags;
+                if mount(None, point, None, flags, None) != 0 {
+                    let err = io::Error::last_os_error();
+                    // Mount points that were removed after being mounted cannot be reached
+                    if err.raw_os_error() != Some(libc::ENOENT) {
+                        return Err(err);
+                    }
+                }
+            }
+
+            let tmpfs_flags = libc::MS_NOSUID | libc::MS_NODEV;
+            for path in &self.hidden_paths {
+                let options = Some(self.tmpfs_options.as_c_str());
+                cvt(mount(Some(c"tmpfs"), path, Some(c"tmpfs"), tmpfs_flags, options))?;
+            }
+
+            // Mount the project directory back where it was
+            for dir in &self.work_dir_parents {
+                if libc::mkdir(dir.as_ptr(), 0o755) != 0 {
+                    let err = io::Error::last_os_error();
+                    if err.raw_os_error() != Some(libc::EEXIST) {
+                        return Err(err);
+                    }
+                }
+            }
+            let mut fd_path = [0u8; 32];
+            let fd_path = fd_path_of(work_fd, &mut fd_path);
+            cvt(mount(Some(fd_path), work_dir, None, libc::MS_BIND, None))?;
+            libc::close(work_fd);
+            cvt(libc::chdir(work_dir.as_ptr()))?;
+
+            // Hide other processes, including the server and its environment
+            let proc_flags = libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC | libc::MS_RDONLY;
+            cvt(mount(Some(c"tmpfs"), c"/proc", Some(c"tmpfs"), proc_flags, None))?;
+
+            drop_capabilities()?;
+
+            cvt(libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0))?;
+            let program = libc::sock_fprog {
+                len: self.filter.len() as libc::c_ushort,
+                filter: self.filter.as_ptr() as *mut libc::sock_filter,
+            };
+            cvt(
+                libc::prctl(
+                    libc::PR_SET_SECCOMP,
+                    libc::SECCOMP_MODE_FILTER,
+                    &program as *const libc::sock_fprog
+                )
+            )?;
+
+            run_in_pid_namespace()
+        }
+    }
+}
+
+// Function to move the rest of the run into the PID namespace, where nargo can only see and
+// signal the processes of its own run. Only children join the namespace, so the calling process
+// stays outside and returns in a child that goes on to exec nargo, while it waits for that child
+// and exits the same way. The namespace's first process, PID 1, only reaps orphans: the kernel
+// ignores signals PID 1 does not handle, such as SIGXCPU and SIGXFSZ, so nargo must not be it.
+// Runs between fork and exec, so it must only make async-signal-safe calls.
+unsafe fn run_in_pid_namespace() -> io::Result<()> {
+    let init = libc::fork();
+    if init < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if init == 0 {
+        // Leave nothing open, so the server sees the end of nargo's output when nargo ends, and
+        // die with the namespace's parent
+        close_from(0);
+        libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL, 0, 0, 0);
+        libc::signal(libc::SIGCHLD, libc::SIG_IGN);
+        loop {
+            libc::pause();
+        }
+    }
+
+    let child = libc::fork();
+    if child < 0 {
+        let err = io::Error::last_os_error();
+        libc::kill(init, libc::SIGKILL);
+        return Err(err);
+    }
+    if child == 0 {
+        return Ok(());
+    }
+
+    // Only the child may keep the pipe the server learns of a failed exec through
+    close_from(3);
+    let mut status = 0;
+    while libc::waitpid(child, &mut status, 0) < 0 {
+        if io::Error::last_os_error().raw_os_error() != Some(libc::EINTR) {
+            libc::_exit(127);
+        }
+    }
+    // Ending PID 1 kills anything left in the namespace
+    libc::kill(init, libc::SIGKILL);
+    libc::waitpid(init, std::ptr::null_mut(), 0);
+
+    if libc::WIFSIGNALED(status) {
+        let signal = libc::WTERMSIG(status);
+        libc::signal(signal, libc::SIG_DFL);
+        let mut unblocked = std::mem::zeroed();
+        libc::sigemptyset(&mut unblocked);
+        libc::sigaddset(&mut unblocked, signal);
+        libc::sigprocmask(libc::SIG_UNBLOCK, &unblocked, std::ptr::null_mut());
+        libc::kill(libc::getpid(), signal);
+        libc::_exit(128 + signal);
+    }
+    libc::_exit(libc::WEXITSTATUS(status))
+}
+
+// Function to close every file descriptor from first up
+unsafe fn close_from(first: libc::c_uint) {
+    if libc::syscall(libc::SYS_close_range, first, libc::c_uint::MAX, 0) == 0 {
+        return;
+    }
+    // Kernels before 5.9 have no close_range
+    let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+    let max = if libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) == 0 {
+        limit.rlim_cur.min(65536) as libc::c_int
+    } else {
+        1024
+    };
+    for fd in (first as libc::c_int)..max {
+        libc::close(fd);
+    }
+}
+
+fn cvt(result: libc::c_int) -> io::Result<()> {
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+unsafe fn mount(
+    source: Option<&CStr>,
+    target: &CStr,
+    fstype: Option<&CStr>,
+    flags: libc::c_ulong,
+    data: Option<&CStr>
+) -> libc::c_int {
+    let ptr = |s: Option<&CStr>| s.map_or(std::ptr::null(), |s| s.as_ptr());
+    libc::mount(ptr(source), target.as_ptr(), ptr(fstype), flags, ptr(data) as *const libc::c_void)
+}
+
+pub(crate) unsafe fn write_file(path: &CStr, content: &CStr) -> io::Result<()> {
+    let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let bytes = content.to_bytes();
+    let written = libc::write(fd, bytes.as_ptr() as *const libc::c_void, bytes.len());
+    let err = io::Error::last_os_error();
+    libc::close(fd);
+    if written != (bytes.len() as isize) {
+        return Err(err);
+    }
+    Ok(())
+}
+
+// Function to write `/proc/self/fd/<fd>` into buf without allocating
+fn fd_path_of(fd: libc::c_int, buf: &mut [u8; 32]) -> &CStr {
+    const PREFIX: &[u8] = b"/proc/self/fd/";
+    buf[..PREFIX.len()].copy_from_slice(PREFIX);
+    let mut digits = [0u8; 10];
+    let mut count = 0;
+    let mut n = fd as u32;
+    loop {
+        digits[count] = b'0' + ((n % 10) as u8);
+        count += 1;
+        n /= 10;
+        if n == 0 {
+            break;
+        }
+    }
+    for i in 0..count {
+        buf[PREFIX.len() + i] = digits[count - 1 - i];
+    }
+    buf[PREFIX.len() + count] = 0;
+    CStr::from_bytes_until_nul(buf).unwrap()
+}
+
+#[repr(C)]
+struct CapHeader {
+    version: u32,
+    pid: libc::c_int,
+}
+
+#[repr(C)]
+struct CapData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+// Function to drop every capability, including from the bounding set so that none can be
+// regained when nargo is executed
+unsafe fn drop_capabilities() -> io::Result<()> {
+    const LINUX_CAPABILITY_VERSION_3: u32 = 0x20080522;
+    // Capabilities the kernel does not know about fail with EINVAL and are already absent
+    for cap in 0..64 {
+        libc::prctl(libc::PR_CAPBSET_DROP, cap, 0, 0, 0);
+    }
+    libc::prctl(libc::PR_CAP_AMBIENT, libc::PR_CAP_AMBIENT_CLEAR_ALL, 0, 0, 0);
+    let header = CapHeader { version: LINUX_CAPABILITY_VERSION_3, pid: 0 };
+    let data = [
+        CapData { effective: 0, permitted: 0, inheritable: 0 },
+        CapData { effective: 0, permitted: 0, inheritable: 0 },
+    ];
+    if libc::syscall(libc::SYS_capset, &header as *const CapHeader, data.as_ptr()) != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000_003e;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xc000_00b7;
+
+// Syscalls refused with EPERM. Besides anything that could change the isolation or reach into
+// other processes, creating sockets is refused so that Unix sockets on the read-only filesystem
+// cannot be connected to either.
+const DENIED_SYSCALLS: &[libc::c_long] = &[
+    libc::SYS_mount,
+    libc::SYS_umount2,
+    libc::SYS_pivot_root,
+    libc::SYS_chroot,
+    libc::SYS_unshare,
+    libc::SYS_setns,
+    libc::SYS_open_tree,
+    libc::SYS_move_mount,
+    libc::SYS_fsopen,
+    libc::SYS_ptrace,
+    libc::SYS_process_vm_readv,
+    libc::SYS_process_vm_writev,
+    libc::SYS_open_by_handle_at,
+    libc::SYS_name_to_handle_at,
+    libc::SYS_socket,
+    libc::SYS_bpf,
+    libc::SYS_perf_event_open,
+    libc::SYS_userfaultfd,
+    libc::SYS_io_uring_setup,
+    libc::SYS_keyctl,
+    libc::SYS_add_key,
+    libc::SYS_request_key,
+    libc::SYS_init_module,
+    libc::SYS_finit_module,
+    libc::SYS_delete_module,
+    libc::SYS_kexec_load,
+    libc::SYS_kexec_file_load,
+    libc::SYS_reboot,
+    libc::SYS_swapon,
+    libc::SYS_swapoff,
+    libc::SYS_acct,
+    libc::SYS_sethostname,
+    libc::SYS_setdomainname,
+];
+
+// Function to build the seccomp program. Syscalls from another architecture are refused
+// outright, as their numbers mean something else.
+fn seccomp_filter() -> Vec<libc::sock_filter> {
+    let statement = |code: u32, k: u32| libc::sock_filter { code: code as u16, jt: 0, jf: 0, k };
+    let jump = |code: u32, k: u32, jt: u8, jf: u8| libc::sock_filter {
+        code: (libc::BPF_JMP | code | libc::BPF_K) as u16,
+        jt,
+        jf,
+        k,
+    };
+    // Offsets into struct seccomp_data
+    const NR: u32 = 0;
+    const ARCH: u32 = 4;
+
+    let mut filter = vec![
+        statement(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, ARCH),
+        jump(libc::BPF_JEQ, AUDIT_ARCH, 1, 0),
+        statement(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+        statement(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, NR)
+    ];
+    // x32 syscalls share the x86_64 architecture but set this bit in their number
+    if cfg!(target_arch = "x86_64") {
+        filter.push(jump(libc::BPF_JGE, 0x4000_0000, 0, 1));
+        filter.push(statement(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS));
+    }
+    for nr in DENIED_SYSCALLS {
+        filter.push(jump(libc::BPF_JEQ, *nr as u32, 0, 1));
+        filter.push(
+            statement(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ERRNO | (libc::EPERM as u32))
+        );
+    }
+    filter.push(statement(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ALLOW));
+    filter
+}
+
+pub(crate) fn c_path(path: &Path) -> io::Result<CString> {
+    CString::new(path.as_os_str().as_bytes()).map_err(|e| {
+        io::Error::new(io::ErrorKind::InvalidInput, e)
+    })
+}
+
+// Function to list every mount point with the per-mount flags the kernel will not let a
+// remount inside the namespace clear
+fn parse_mountinfo(mountinfo: &str) -> io::Result<Vec<(CString, libc::c_ulong)>> {
+    let mut mounts = vec![];
+    for line in mountinfo.lines() {
+        let fields: Vec<&str> = line.split(' ').collect();
+        if fields.len() < 6 {
+            continue;
+        }
+        let mut flags = 0;
+        for option in fields[5].split(',') {
+            flags |= match option {
+                "nosuid" => libc::MS_NOSUID,
+                "nodev" => libc::MS_NODEV,
+                "noexec" => libc::MS_NOEXEC,
+                "noatime" => libc::MS_NOATIME,
+                "nodiratime" => libc::MS_NODIRATIME,
+                "relatime" => libc::MS_RELATIME,
+                "strictatime" => libc::MS_STRICTATIME,
+                _ => 0,
+            };
+        }
+        mounts.push((c_path(&unescape(fields[4]))?, flags));
+    }
+    Ok(mounts)
+}
+
+// Function to decode the octal escapes mountinfo uses for spaces and other special characters
+fn unescape(field: &str) -> PathBuf {
+    let bytes = field.as_bytes();
+    let mut decoded = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = bytes.get(i + 1..i + 4).filter(|digits| {
+            bytes[i] == b'\\' && digits.iter().all(|d| (b'0'..=b'7').contains(d))
+        });
+        match escape {
+            Some(digits) => {
+                decoded.push(digits.iter().fold(0u8, |n, d| n * 8 + (d - b'0')));
+                i += 4;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    PathBuf::from(std::ffi::OsStr::from_bytes(&decoded))
+}
@@ -0,0 +1,329 @@
+use crate::cache::CacheStatus;
+use crate::error::ErrorBody;
+use crate::queue::JobQueue;
+use crate::report::StepReport;
+use crate::sandbox::{ env_u64, LineSink, Stream };
+use crate::util::lock;
+use serde_derive::{ Deserialize, Serialize };
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{ Arc, Mutex };
+use std::time::{ Duration, Instant };
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+// What a job runs: the full grading of POST /execute, or the tests of POST /execute_test
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum JobKind {
+    #[default]
+    Execute,
+    Test,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    // The job ran to the end, and its result says whether the code passed
+    Finished,
+    // The job could not be run, for example because the code hit a resource limit
+    Failed,
+    Cancelled,
+}
+
+// What a run has done so far. Grading reports to it as it goes, so a job's partial results can
+// be shown or streamed. Clones share the same progress.
+#[derive(Clone)]
+pub struct Progress {
+    shared: Arc<ProgressShared>,
+}
+
+struct ProgressShared {
+    state: Mutex<ProgressState>,
+    events: broadcast::Sender<ProgressEvent>,
+}
+
+#[derive(Default)]
+struct ProgressState {
+    steps: Vec<StepReport>,
+    // The step that is running and the lines it has written so far
+    current: Option<(String, Vec<(Stream, String)>)>,
+    done: bool,
+}
+
+// Something that happened during a run, as streamed by GET /jobs/{id}/events
+#[derive(Clone)]
+pub enum ProgressEvent {
+    StepStarted(String),
+    Line {
+        step: String,
+        stream: Stream,
+        line: String,
+    },
+    StepFinished(StepReport),
+    Done,
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        // Subscribers that fall this far behind miss lines rather than hold up nargo
+        let (events, _) = broadcast::channel(1024);
+        Progress {
+            shared: Arc::new(ProgressShared { state: Mutex::default(), events }),
+        }
+    }
+}
+
+impl Progress {
+    pub fn step_started(&self, name: &str) {
+        let mut state = lock(&self.shared.state);
+        state.current = Some((name.to_string(), vec![]));
+        self.send(ProgressEvent::StepStarted(name.to_string()));
+    }
+
+    // Function to get a sink that records the running step's output lines
+    pub fn line_sink(&self) -> LineSink {
+        let progress = self.clone();
+        Arc::new(move |stream, line| progress.line(stream, line))
+    }
+
+    fn line(&self, stream: Stream, line: &str) {
+        let mut state = lock(&self.shared.state);
+        let Some((step, lines)) = state.current.as_mut() else {
+            return;
+        };
+        // Keep indentation, which lines up compiler diagnostics
+        let stripped = strip_ansi_escapes::strip(line).unwrap_or_else(|_| line.as_bytes().to_vec());
+        let line = String::from_utf8_lossy(&stripped).trim_end().to_string();
+        lines.push((stream, line.clone()));
+        let step = step.clone();
+        self.send(ProgressEvent::Line { step, stream, line });
+    }
+
+    pub fn step(&self, step: &StepReport) {
+        let mut state = lock(&self.shared.state);
+        state.current = None;
+        state.steps.push(step.clone());
+        self.send(ProgressEvent::StepFinished(step.clone()));
+    }
+
+    pub fn done(&self) {
+        let mut state = lock(&self.shared.state);
+        state.done = true;
+        self.send(ProgressEvent::Done);
+    }
+
+    // Function to subscribe to events, also returning the events that already happened so a
+    // late subscriber can catch up
+    pub fn subscribe(&self) -> (Vec<ProgressEvent>, broadcast::Receiver<ProgressEvent>) {
+        // Holding the lock means no event can be sent between the replay and the subscription
+        let state = lock(&self.shared.state);
+        let mut replay: Vec<ProgressEvent> = state.steps
+            .iter()
+            .map(|step| ProgressEvent::StepFinished(step.clone()))
+            .collect();
+        if let Some((step, lines)) = &state.current {
+            replay.push(ProgressEvent::StepStarted(step.clone()));
+            replay.extend(
+                lines.iter().map(|(stream, line)| ProgressEvent::Line {
+                    step: step.clone(),
+                    stream: *stream,
+                    line: line.clone(),
+                })
+            );
+        }
+        if state.done {
+            replay.push(ProgressEvent::Done);
+        }
+        (replay, self.shared.events.subscribe())
+    }
+
+    // Function to send an event, which must be called with the state locked
+    fn send(&self, event: ProgressEvent) {
+        // Sending only fails when nobody is subscribed
+        let _ = self.shared.events.send(event);
+    }
+}
+
+// A job's state returned by the job routes
+#[derive(Serialize)]
+pub struct JobView {
+    pub id: Uuid,
+    pub kind: JobKind,
+    pub status: JobStatus,
+    // 1-based position in the queue while the job is waiting to run
+    pub position: Option<usize>,
+    // Steps finished so far
+    pub steps: Vec<StepReport>,
+    // The grading or test report, once the job has finished
+    pub result: Option<serde_json::Value>,
+    pub error: Option<ErrorBody>,
+    // Whether the result came from the cache, once the job has looked it up
+    pub cache: Option<CacheStatus>,
+}
+
+struct Job {
+    kind: JobKind,
+    // None if the job never had to wait in the queue
+    ticket_id: Option<u64>,
+    progress: Progress,
+    status: JobStatus,
+    result: Option<serde_json::Value>,
+    error: Option<ErrorBody>,
+    cache: Option<CacheStatus>,
+    finished_at: Option<Instant>,
+    task: Option<JoinHandle<()>>,
+}
+
+// Jobs submitted through POST /jobs. Finished jobs are kept for JOB_RETENTION_SECONDS
+// (default 600) so their results can be fetched.
+pub struct JobStore {
+    queue: Arc<JobQueue>,
+    retention: Duration,
+    jobs: Mutex<HashMap<Uuid, Job>>,
+}
+
+impl JobStore {
+    pub fn new(queue: Arc<JobQueue>) -> Self {
+        JobStore {
+            queue,
+            retention: Duration::from_secs(env_u64("JOB_RETENTION_SECONDS", 600)),
+            jobs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Function to start a job. `run` is spawned straight away and is expected to wait for its
+    // turn in the queue; it reports to `progress` and returns the job's result.
+    pub fn spawn<F>(
+        self: &Arc<Self>,
+        id: Uuid,
+        kind: JobKind,
+        ticket_id: Option<u64>,
+        progress: Progress,
+        run: F
+    ) -> JobView
+        where F: Future<Output = Result<serde_json::Value, ErrorBody>> + Send + 'static
+    {
+        let mut jobs = lock(&self.jobs);
+        self.remove_expired(&mut jobs);
+        jobs.insert(id, Job {
+            kind,
+            ticket_id,
+            progress,
+            status: JobStatus::Queued,
+            result: None,
+            error: None,
+            cache: None,
+            finished_at: None,
+            task: None,
+        });
+
+        let store = Arc::clone(self);
+        let task = tokio::spawn(async move {
+            let result = run.await;
+            store.finish(id, result);
+        });
+        // The task cannot finish before this, as it needs the lock to record its result
+        let job = jobs.get_mut(&id).unwrap();
+        job.task = Some(task);
+        self.view(id, job)
+    }
+
+    // Function to mark a queued job as running, once it has its turn
+    pub fn started(&self, id: Uuid) {
+        if let Some(job) = lock(&self.jobs).get_mut(&id) {
+            job.status = JobStatus::Running;
+        }
+    }
+
+    // Function to record whether a job's result came from the cache
+    pub fn cache_status(&self, id: Uuid, status: CacheStatus) {
+        if let Some(job) = lock(&self.jobs).get_mut(&id) {
+            job.cache = Some(status);
+        }
+    }
+
+    pub fn get(&self, id: Uuid) -> Option<JobView> {
+        let mut jobs = lock(&self.jobs);
+        self.remove_expired(&mut jobs);
+        jobs.get(&id).map(|job| self.view(id, job))
+    }
+
+    // Function to cancel a queued or running job, killing its nargo process and removing its
+    // project directory. A job that already ended is forgotten instead.
+    pub async fn cancel(&self, id: Uuid) -> Option<JobView> {
+        let (task, progress) = {
+            let mut jobs = lock(&self.jobs);
+            let job = jobs.get_mut(&id)?;
+            if job.finished_at.is_some() {
+                let job = jobs.remove(&id).unwrap();
+                return Some(self.view(id, &job));
+            }
+            job.status = JobStatus::Cancelled;
+            job.finished_at = Some(Instant::now());
+            (job.task.take(), job.progress.clone())
+        };
+
+        // Dropping the job's future kills its nargo process, removes its project directory and
+        // gives up its place in the queue
+        if let Some(task) = task {
+            task.abort();
+            let _ = task.await;
+        }
+        progress.done();
+        self.get(id)
+    }
+
+    // Function to get a job's progress, to stream its events
+    pub fn progress(&self, id: Uuid) -> Option<Progress> {
+        lock(&self.jobs).get(&id).map(|job| job.progress.clone())
+    }
+
+    fn finish(&self, id: Uuid, result: Result<serde_json::Value, ErrorBody>) {
+        let mut jobs = lock(&self.jobs);
+        let Some(job) = jobs.get_mut(&id) else {
+            return;
+        };
+        if job.status == JobStatus::Cancelled {
+            return;
+        }
+        match result {
+            Ok(result) => {
+                job.status = JobStatus::Finished;
+                job.result = Some(result);
+            }
+            Err(error) => {
+                job.status = JobStatus::Failed;
+                job.error = Some(error);
+            }
+        }
+        job.finished_at = Some(Instant::now());
+        job.task = None;
+        job.progress.done();
+    }
+
+    fn view(&self, id: Uuid, job: &Job) -> JobView {
+        let position = match job.status {
+            JobStatus::Queued => job.ticket_id.and_then(|ticket_id| self.queue.position(ticket_id)),
+            _ => None,
+        };
+        JobView {
+            id,
+            kind: job.kind,
+            status: job.status,
+            position,
+            steps: lock(&job.progress.shared.state).steps.clone(),
+            result: job.result.clone(),
+            error: job.error.clone(),
+            cache: job.cache,
+        }
+    }
+
+    fn remove_expired(&self, jobs: &mut HashMap<Uuid, Job>) {
+        let retention = self.retention;
+        jobs.retain(|_, job| job.finished_at.is_none_or(|at| at.elapsed() < retention));
+    }
+}
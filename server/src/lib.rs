@@ -0,0 +1,1016 @@
+pub mod artifacts;
+pub mod cache;
+pub mod cgroups;
+pub mod challenges;
+pub mod curriculum;
+pub mod diagnostics;
+pub mod error;
+pub mod inputs;
+pub mod isolation;
+pub mod jobs;
+pub mod lint;
+pub mod queue;
+pub mod report;
+pub mod sandbox;
+pub mod sources;
+pub mod template;
+pub mod toolchain;
+pub mod util;
+pub mod validate;
+pub mod vectors;
+pub mod workspace;
+use artifacts::ArtifactCache;
+use cache::{ CacheStatus, Cached, Lookup, ResultCache };
+use challenges::{ ChallengeStore, Difficulty, HelperModule };
+use error::ApiError;
+use inputs::{ prover_toml, test_filter, ProverInputs };
+use futures_util::stream;
+use jobs::{ JobKind, JobStore, JobView, Progress, ProgressEvent };
+use queue::{ JobQueue, Permit };
+use sandbox::{ LineSink, RunError, Stream };
+use sources::{ SourceMap, Sources };
+use toolchain::{ NargoCommand, Toolchain };
+use report::{ GradeReport, StepReport, StepStatus, TestReport };
+use serde_derive::{ Deserialize, Serialize };
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::net::{ IpAddr, Ipv4Addr, SocketAddr };
+use std::path::Path;
+use std::process::Output;
+use std::sync::Arc;
+use std::time::{ Duration, Instant };
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task;
+use uuid::Uuid;
+use vectors::run_vectors;
+use workspace::{ Workspace, WorkspaceManager };
+use warp::reply::{ Json, Response };
+use warp::sse;
+use warp::{ Filter, Rejection, Reply };
+
+// Structure to receive data
+#[derive(Deserialize)]
+struct ExecutionInput {
+    code: String,
+    challenge_id: u32,
+    // Any JSON values, written to Prover.toml as the TOML types nargo expects
+    prover_inputs: ProverInputs,
+    // Only run tests whose name contains this, used by POST /execute_test
+    #[serde(default)]
+    test_name: Option<String>,
+}
+
+// Structure to receive a job, which is an execution plus what kind of run it is
+#[derive(Deserialize)]
+struct JobInput {
+    #[serde(default)]
+    kind: JobKind,
+    #[serde(flatten)]
+    input: ExecutionInput,
+}
+
+#[derive(Serialize)]
+struct Challenge {
+    id: u32,
+    title: String,
+    description: String,
+    starter_code: String,
+    helpers: Vec<HelperModule>,
+    test_cases: Vec<String>,
+}
+
+// Summary of a challenge returned by the listing endpoint, without description or test cases
+#[derive(Serialize)]
+struct ChallengeSummary {
+    id: u32,
+    title: String,
+    track: String,
+    difficulty: Difficulty,
+    tags: Vec<String>,
+    estimated_minutes: Option<u32>,
+    prerequisites: Vec<u32>,
+}
+
+// Query parameters accepted by the listing endpoint
+#[derive(Deserialize)]
+struct ChallengeListQuery {
+    tag: Option<String>,
+    difficulty: Option<Difficulty>,
+}
+
+#[derive(Serialize)]
+struct ReloadResponse {
+    challenges: usize,
+}
+
+// Function to build the server's routes. Challenges are served from store, and nargo is run
+// through toolchain, in a workspace from workspaces, once the run's turn comes up in job_queue,
+// unless the result is already in cache. Source compiled before has its compiled circuit and
+// test and check steps restored from artifacts.
+// POST /admin/reload is only enabled when there is an admin_token.
+pub fn routes(
+    store: Arc<ChallengeStore>,
+    job_queue: Arc<JobQueue>,
+    toolchain: Arc<dyn Toolchain>,
+    workspaces: Arc<WorkspaceManager>,
+    cache: Arc<ResultCache>,
+    artifacts: Arc<ArtifactCache>,
+    admin_token: Option<String>
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    // Every request works on the challenge set that was current when it arrived
+    let store_for_snapshot = Arc::clone(&store);
+    let challenges = warp::any().map(move || store_for_snapshot.snapshot());
+
+    // Requests that run nargo wait for a free worker, taking turns by client address
+    let queue_for_filter = Arc::clone(&job_queue);
+    let queue = warp
+        ::addr::remote()
+        .and(warp::any().map(move || Arc::clone(&queue_for_filter)));
+    let toolchain = warp::any().map(move || Arc::clone(&toolchain));
+    let workspaces = warp::any().map(move || Arc::clone(&workspaces));
+    let cache = warp::any().map(move || Arc::clone(&cache));
+    let artifacts = warp::any().map(move || Arc::clone(&artifacts));
+
+    // Routes and CORS
+    let execute = warp
+        ::post()
+        .and(warp::path("execute"))
+        .and(warp::body::json())
+        .and(challenges.clone())
+        .and(queue.clone())
+        .and(toolchain.clone())
+        .and(workspaces.clone())
+        .and(cache.clone())
+        .and(artifacts.clone())
+        .and_then(execute_code);
+
+    let execute_test = warp
+        ::post()
+        .and(warp::path("execute_test"))
+        .and(warp::body::json())
+        .and(challenges.clone())
+        .and(queue.clone())
+        .and(toolchain.clone())
+        .and(workspaces.clone())
+        .and(cache.clone())
+        .and_then(execute_test_code);
+
+    let execute_check = warp
+        ::post()
+        .and(warp::path("execute_check"))
+        .and(warp::body::json())
+        .and(challenges.clone())
+        .and(queue.clone())
+        .and(toolchain.clone())
+        .and(workspaces.clone())
+        .and(cache.clone())
+        .and_then(execute_check);
+
+    let challenges_route = warp
+        ::path("challenges")
+        .and(warp::path::param::<u32>())
+        .and(warp::path::end())
+        .and(challenges.clone())
+        .and_then(get_challenge);
+
+    let list_challenges_route = warp
+        ::get()
+        .and(warp::path("challenges"))
+        .and(warp::path::end())
+        .and(warp::query::<ChallengeListQuery>())
+        .and(challenges.clone())
+        .and_then(list_challenges);
+
+    let tracks_route = warp
+        ::get()
+        .and(warp::path("tracks"))
+        .and(warp::path::end())
+        .and(challenges.clone())
+        .and_then(get_tracks);
+
+    // Jobs run in the background and are polled for their result
+    let jobs = Arc::new(JobStore::new(Arc::clone(&job_queue)));
+    let jobs = warp::any().map(move || Arc::clone(&jobs));
+
+    let create_job_route = warp
+        ::post()
+        .and(warp::path("jobs"))
+        .and(warp::path::end())
+        .and(warp::body::json())
+        .and(challenges.clone())
+        .and(queue.clone())
+        .and(toolchain.clone())
+        .and(workspaces.clone())
+        .and(cache.clone())
+        .and(artifacts.clone())
+        .and(jobs.clone())
+        .and_then(create_job);
+
+    let get_job_route = warp
+        ::get()
+        .and(warp::path!("jobs" / Uuid))
+        .and(jobs.clone())
+        .and_then(get_job);
+
+    let job_events_route = warp
+        ::get()
+        .and(warp::path!("jobs" / Uuid / "events"))
+        .and(jobs.clone())
+        .and_then(job_events);
+
+    let cancel_job_route = warp
+        ::delete()
+        .and(warp::path!("jobs" / Uuid))
+        .and(jobs.clone())
+        .and_then(cancel_job);
+
+    let queue_route = warp
+        ::get()
+        .and(warp::path("queue"))
+        .and(warp::path::end())
+        .map(move || warp::reply::json(&job_queue.status()));
+
+    // Admin route to force a reload, only enabled when there is an admin token
+    let reload_route = warp
+        ::post()
+        .and(warp::path!("admin" / "reload"))
+        .and(warp::header::optional::<String>("x-admin-token"))
+        .and(warp::any().map(move || admin_token.clone()))
+        .and(warp::any().map(move || Arc::clone(&store)))
+        .and_then(reload_challenges);
+
+    let cors = warp
+        ::cors()
+        .allow_any_origin()
+        .allow_headers(vec!["Accept", "Content-Type"])
+        .allow_methods(vec!["GET", "POST", "DELETE", "PUT", "HEAD", "OPTIONS"])
+        .expose_headers(
+            vec![
+                "Retry-After",
+                "X-Queue-Position",
+                "X-Queue-Wait-Ms",
+                "X-Cache",
+                "X-Cache-Key",
+                "X-Cache-Age-Ms"
+            ]
+        );
+
+    execute
+        .or(execute_test)
+        .or(challenges_route)
+        .or(list_challenges_route)
+        .or(tracks_route)
+        .or(execute_check)
+        .or(reload_route)
+        .or(queue_route)
+        .or(create_job_route)
+        .or(get_job_route)
+        .or(job_events_route)
+        .or(cancel_job_route)
+        .recover(handle_rejection)
+        .with(cors)
+}
+
+// Function to list challenges ordered by ID, optionally filtered by tag and difficulty
+async fn list_challenges(
+    query: ChallengeListQuery,
+    challenges: Arc<Vec<challenges::Challenge>>
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let summaries: Vec<ChallengeSummary> = challenges
+        .iter()
+        .filter(|c| {
+            query.tag
+                .as_ref()
+                .is_none_or(|tag| c.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)))
+        })
+        .filter(|c| query.difficulty.is_none_or(|difficulty| c.difficulty == difficulty))
+        .map(|c| ChallengeSummary {
+            id: c.id,
+            title: c.title.clone(),
+            track: c.track.clone(),
+            difficulty: c.difficulty,
+            tags: c.tags.clone(),
+            estimated_minutes: c.estimated_minutes,
+            prerequisites: c.prerequisites.clone(),
+        })
+        .collect();
+    Ok(warp::reply::json(&summaries))
+}
+
+// Function to get the curriculum as tracks of challenges ordered by their prerequisites
+async fn get_tracks(
+    challenges: Arc<Vec<challenges::Challenge>>
+) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(warp::reply::json(&curriculum::build_tracks(&challenges)))
+}
+
+// Function to get challenge from ID
+async fn get_challenge(
+    id: u32,
+    challenges: Arc<Vec<challenges::Challenge>>
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let challenge = challenges.iter().find(|c| c.id == id);
+    match challenge {
+        Some(challenge) => {
+            let send_challenge = Challenge {
+                id: challenge.id,
+                title: challenge.title.clone(),
+                description: challenge.description.clone(),
+                starter_code: challenge.starter_code.clone(),
+                helpers: challenge.helpers.clone(),
+                test_cases: vec![], // Empty test cases when returning to the user
+            };
+            Ok(warp::reply::json(&send_challenge))
+        }
+        None => Err(ApiError::ChallengeNotFound(id).into()),
+    }
+}
+
+// Function to reload challenge packages on demand
+async fn reload_challenges(
+    token: Option<String>,
+    admin_token: Option<String>,
+    store: Arc<ChallengeStore>
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match admin_token {
+        Some(admin_token) if token.as_deref() == Some(admin_token.as_str()) => {}
+        _ => {
+            return Err(warp::reject::not_found());
+        }
+    }
+
+    let result = task
+        ::spawn_blocking(move || store.reload()).await
+        .map_err(|_| ApiError::Internal("Failed to perform task".into()))?;
+    match result {
+        Ok(count) => Ok(warp::reply::json(&ReloadResponse { challenges: count })),
+        Err(errors) => {
+            let message = errors
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+                .join("\n");
+            Err(ApiError::Internal(message).into())
+        }
+    }
+}
+
+// Function to create a workspace for a new Noir project, which is removed when it is dropped
+async fn create_workspace(workspaces: &WorkspaceManager) -> Result<Workspace<'_>, ApiError> {
+    workspaces.create().await.map_err(ApiError::from)
+}
+
+// Function to run a nargo command in specified directory, returning its output whether or not
+// it exited successfully. Failing to run the command at all, or the command hitting a resource
+// limit, is an error. If the returned future is dropped, for example because a job was
+// cancelled, the command is stopped.
+async fn run_command(
+    toolchain: &dyn Toolchain,
+    command: NargoCommand,
+    dir_buf: &Path
+) -> Result<Output, ApiError> {
+    run_command_streamed(toolchain, command, dir_buf, None).await
+}
+
+// Function to run a command like run_command, also passing each line of output to on_line
+// as soon as it is written
+async fn run_command_streamed(
+    toolchain: &dyn Toolchain,
+    command: NargoCommand,
+    dir_buf: &Path,
+    on_line: Option<LineSink>
+) -> Result<Output, ApiError> {
+    match toolchain.run(dir_buf, command, on_line).await {
+        Ok(output) => Ok(output),
+        Err(RunError::Limit(e)) => Err(ApiError::Limit(e)),
+        Err(RunError::Io(e)) => Err(ApiError::Internal(e.to_string())),
+    }
+}
+
+// Function to run nargo check on user submitted code
+async fn execute_check(
+    body: ExecutionInput,
+    challenges: Arc<Vec<challenges::Challenge>>,
+    client: Option<SocketAddr>,
+    queue: Arc<JobQueue>,
+    toolchain: Arc<dyn Toolchain>,
+    workspaces: Arc<WorkspaceManager>,
+    cache: Arc<ResultCache>
+) -> Result<Response, Rejection> {
+    let challenge = find_challenge(&challenges, body.challenge_id)?;
+    let key = cache.key("check", challenge, &body.code, "");
+    let pending = match cache.lookup(&key).await {
+        Lookup::Cached(cached) => {
+            return Ok(cached_reply(cached, &key));
+        }
+        Lookup::Run(pending) => pending,
+    };
+    let permit = acquire_worker(&queue, client).await?;
+    let workspace = create_workspace(&workspaces).await?;
+    let project_dir = workspace.dir();
+    println!("Project directory in execute function: {:?}", project_dir);
+
+    let code_file_path = project_dir.join("project/src/main.nr");
+
+    // Write code to src/main.nr file
+    tokio::fs
+        ::write(&code_file_path, &body.code).await
+        .map_err(|e| {
+            ApiError::Internal(format!("Failed to write to file: {}", e))
+        })?;
+    write_helper_modules(project_dir, challenge).await?;
+
+    let project_sub_dir = workspace.project();
+
+    let output = run_command(toolchain.as_ref(), NargoCommand::Check, &project_sub_dir).await?;
+
+    if !output.status.success() {
+        // The learner's code does not compile, so pass on what nargo said about it
+        let stderr = report::strip_ansi(&output.stderr);
+        let message = match stderr.trim() {
+            "" => String::from("Failed to execute check"),
+            stderr => format!("Failed to execute check:\n{}", stderr),
+        };
+        return Err(ApiError::Code(message, diagnostics::parse(&stderr)).into());
+    }
+
+    // Read the contents of Prover.toml
+    let prover_file_path = project_dir.join("project/Prover.toml");
+    let prover_content = tokio::fs
+        ::read_to_string(&prover_file_path).await
+        .map_err(|e| {
+            ApiError::Internal(format!("Failed to read Prover.toml: {}", e))
+        })?;
+
+    // Parse it into a JSON object
+    let prover_toml: serde_json::Value = toml
+        ::from_str(&prover_content)
+        .map_err(|err| ApiError::Internal(err.to_string()))?;
+
+    // Return the parsed content
+    pending.store(&prover_toml);
+    let reply = with_queue_headers(warp::reply::json(&prover_toml), &permit);
+    Ok(with_cache_headers(reply, &key, CacheStatus::Miss, Duration::ZERO))
+}
+
+// Function to run nargo test on user submitted code
+async fn execute_test_code(
+    body: ExecutionInput,
+    challenges: Arc<Vec<challenges::Challenge>>,
+    client: Option<SocketAddr>,
+    queue: Arc<JobQueue>,
+    toolchain: Arc<dyn Toolchain>,
+    workspaces: Arc<WorkspaceManager>,
+    cache: Arc<ResultCache>
+) -> Result<Response, Rejection> {
+    // Check if "#[test]" exists in the client-side code:
+    if !body.code.contains("#[test") {
+        let report = TestReport::empty("There are no tests to run");
+        return Ok(warp::reply::json(&report).into_response());
+    }
+    let challenge = find_challenge(&challenges, body.challenge_id)?;
+    let test_name = test_filter(body.test_name.as_deref())?;
+    let key = cache.key("test", challenge, &body.code, test_name.unwrap_or_default());
+    let pending = match cache.lookup(&key).await {
+        Lookup::Cached(cached) => {
+            return Ok(cached_reply(cached, &key));
+        }
+        Lookup::Run(pending) => pending,
+    };
+    let permit = acquire_worker(&queue, client).await?;
+    let progress = Progress::default();
+    let report = run_tests(
+        toolchain.as_ref(),
+        &workspaces,
+        challenge,
+        &body.code,
+        test_name,
+        &progress
+    ).await?;
+    let report = serde_json::to_value(report).unwrap();
+    pending.store(&report);
+    let reply = with_queue_headers(warp::reply::json(&report), &permit);
+    Ok(with_cache_headers(reply, &key, CacheStatus::Miss, Duration::ZERO))
+}
+
+// Function to run the learner's own tests in a fresh project
+async fn run_tests(
+    toolchain: &dyn Toolchain,
+    workspaces: &WorkspaceManager,
+    challenge: &challenges::Challenge,
+    code: &str,
+    test_name: Option<&str>,
+    progress: &Progress
+) -> Result<TestReport, ApiError> {
+    let workspace = create_workspace(workspaces).await?;
+    let project_dir = workspace.dir();
+
+    let code_file_path = project_dir.join("project/src/main.nr");
+
+    // Write user code to src/main.nr file
+    tokio::fs
+        ::write(&code_file_path, code).await
+        .map_err(|e| {
+            ApiError::Internal(format!("Failed to write to file: {}", e))
+        })?;
+    write_helper_modules(project_dir, challenge).await?;
+
+    // Run nargo test
+    let report = run_nargo_test(toolchain, &workspace.project(), test_name, progress).await?;
+    if report.tests.is_empty() && test_name.is_some() && report.passed {
+        return Ok(TestReport::empty("No tests matched the given name"));
+    }
+    Ok(report)
+}
+
+// Function to run nargo test, optionally only for tests matching a name,
+// which is called by run_tests
+async fn run_nargo_test(
+    toolchain: &dyn Toolchain,
+    dir_buf: &Path,
+    test_name: Option<&str>,
+    progress: &Progress
+) -> Result<TestReport, ApiError> {
+    let command = NargoCommand::Test(test_name.map(str::to_string));
+    progress.step_started("test");
+    let started = Instant::now();
+    let on_line = Some(progress.line_sink());
+    let output = run_command_streamed(toolchain, command, dir_buf, on_line).await?;
+    progress.step(&StepReport::from_output("test", &output, started.elapsed()));
+
+    Ok(TestReport::from_output(&output))
+}
+// Function to handle execution of user submitted code,
+// which includes running pre-written test cases
+// and running prover and verifier functions
+#[allow(clippy::too_many_arguments)]
+async fn execute_code(
+    body: ExecutionInput,
+    challenges: Arc<Vec<challenges::Challenge>>,
+    client: Option<SocketAddr>,
+    queue: Arc<JobQueue>,
+    toolchain: Arc<dyn Toolchain>,
+    workspaces: Arc<WorkspaceManager>,
+    cache: Arc<ResultCache>,
+    artifacts: Arc<ArtifactCache>
+) -> Result<Response, Rejection> {
+    println!("Received code: {}", body.code);
+
+    // Find the challenge with the given ID
+    let challenge = find_challenge(&challenges, body.challenge_id)?;
+
+    // Reuse the result of identical code and inputs if it was already worked out
+    let prover_toml = prover_toml(&body.prover_inputs)?;
+    let key = cache.key("execute", challenge, &body.code, &prover_toml);
+    let pending = match cache.lookup(&key).await {
+        Lookup::Cached(cached) => {
+            return Ok(cached_reply(cached, &key));
+        }
+        Lookup::Run(pending) => pending,
+    };
+
+    // Run the code against the challenge's test cases, then prove and verify
+    let permit = acquire_worker(&queue, client).await?;
+    let progress = Progress::default();
+    let report = grade_submission(
+        toolchain.as_ref(),
+        &workspaces,
+        Some(&artifacts),
+        challenge,
+        &body.code,
+        &prover_toml,
+        &progress
+    ).await?;
+    let report = serde_json::to_value(report).unwrap();
+    pending.store(&report);
+    let reply = with_queue_headers(warp::reply::json(&report), &permit);
+    Ok(with_cache_headers(reply, &key, CacheStatus::Miss, Duration::ZERO))
+}
+
+// Function to start a job that runs in the background, returning its ID straight away.
+// The job waits for its turn in the queue like any other execution, unless its result is
+// already cached or there is nothing to run.
+#[allow(clippy::too_many_arguments)]
+async fn create_job(
+    body: JobInput,
+    challenges: Arc<Vec<challenges::Challenge>>,
+    client: Option<SocketAddr>,
+    queue: Arc<JobQueue>,
+    toolchain: Arc<dyn Toolchain>,
+    workspaces: Arc<WorkspaceManager>,
+    cache: Arc<ResultCache>,
+    artifacts: Arc<ArtifactCache>,
+    jobs: Arc<JobStore>
+) -> Result<Response, Rejection> {
+    let JobInput { kind, input } = body;
+    let challenge = find_challenge(&challenges, input.challenge_id)?;
+    let prover_toml = prover_toml(&input.prover_inputs)?;
+    let key = match kind {
+        JobKind::Execute => cache.key("execute", challenge, &input.code, &prover_toml),
+        JobKind::Test => {
+            let test_name = test_filter(input.test_name.as_deref())?.unwrap_or_default();
+            cache.key("test", challenge, &input.code, test_name)
+        }
+    };
+    let id = Uuid::new_v4();
+    let progress = Progress::default();
+
+    // A job with nothing to run, or whose result is already cached, finishes straight away
+    // without taking a place in the queue
+    let ready = match kind {
+        JobKind::Test if !input.code.contains("#[test") => {
+            let report = TestReport::empty("There are no tests to run");
+            Some((serde_json::to_value(report).unwrap(), None))
+        }
+        _ => cache.cached(&key).map(|cached| (cached.value, Some(cached.status))),
+    };
+    if let Some((result, cache_status)) = ready {
+        let job_store = Arc::clone(&jobs);
+        let run = async move {
+            if let Some(status) = cache_status {
+                job_store.cache_status(id, status);
+            }
+            Ok(result)
+        };
+        return Ok(job_accepted(&jobs.spawn(id, kind, None, progress, run)));
+    }
+
+    let client = client.map_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED), |addr| addr.ip());
+    let ticket = queue.enqueue(client).map_err(ApiError::from)?;
+    let ticket_id = ticket.id;
+    let job_progress = progress.clone();
+    let job_store = Arc::clone(&jobs);
+    let run = async move {
+        // An identical job may be working the result out, in which case wait for it instead
+        let pending = match cache.lookup(&key).await {
+            Lookup::Cached(cached) => {
+                job_store.cache_status(id, cached.status);
+                return Ok(cached.value);
+            }
+            Lookup::Run(pending) => pending,
+        };
+        job_store.cache_status(id, CacheStatus::Miss);
+        let _permit = ticket.wait().await;
+        job_store.started(id);
+        let challenge = find_challenge(&challenges, input.challenge_id).map_err(|e| e.body())?;
+        let result = match kind {
+            JobKind::Execute => {
+                grade_submission(
+                    toolchain.as_ref(),
+                    &workspaces,
+                    Some(&artifacts),
+                    challenge,
+                    &input.code,
+                    &prover_toml,
+                    &job_progress
+                ).await.map(|report| serde_json::to_value(report).unwrap())
+            }
+            JobKind::Test => {
+                let test_name = input.test_name.as_deref();
+                run_tests(
+                    toolchain.as_ref(),
+                    &workspaces,
+                    challenge,
+                    &input.code,
+                    test_name,
+                    &job_progress
+                ).await.map(|report| serde_json::to_value(report).unwrap())
+            }
+        };
+        let result = result.map_err(|e| e.body())?;
+        pending.store(&result);
+        Ok(result)
+    };
+
+    Ok(job_accepted(&jobs.spawn(id, kind, Some(ticket_id), progress, run)))
+}
+
+// Function to reply to POST /jobs with the new job and where to find it
+fn job_accepted(job: &JobView) -> Response {
+    let reply = warp::reply::with_status(
+        warp::reply::json(job),
+        warp::http::StatusCode::ACCEPTED
+    );
+    warp::reply::with_header(reply, "Location", format!("/jobs/{}", job.id)).into_response()
+}
+
+// Function to get a job's status, its steps so far and, once finished, its result
+async fn get_job(id: Uuid, jobs: Arc<JobStore>) -> Result<Json, Rejection> {
+    match jobs.get(id) {
+        Some(job) => Ok(warp::reply::json(&job)),
+        None => Err(ApiError::NotFound("No job found with the given ID".into()).into()),
+    }
+}
+
+// Function to stream a job's progress as server-sent events: `step-start`, a `stdout` or
+// `stderr` event for each line nargo writes, `step-finish` with the step's report, and finally
+// `done` with the job as GET /jobs/{id} returns it. A client that connects late is sent what
+// already happened first.
+async fn job_events(id: Uuid, jobs: Arc<JobStore>) -> Result<Response, Rejection> {
+    let progress = jobs
+        .progress(id)
+        .ok_or_else(|| ApiError::NotFound("No job found with the given ID".into()))?;
+    let (replay, receiver) = progress.subscribe();
+
+    let events = stream::unfold(
+        (VecDeque::from(replay), receiver, false),
+        move |(mut replay, mut receiver, finished)| {
+            let jobs = Arc::clone(&jobs);
+            async move {
+                if finished {
+                    return None;
+                }
+                let event = match replay.pop_front() {
+                    Some(event) => event,
+                    None =>
+                        loop {
+                            match receiver.recv().await {
+                                Ok(event) => {
+                                    break event;
+                                }
+                                // Lines missed by a slow client are skipped
+                                Err(RecvError::Lagged(_)) => {
+                                    continue;
+                                }
+                                Err(RecvError::Closed) => {
+                                    break ProgressEvent::Done;
+                                }
+                            }
+                        }
+                };
+                let finished = matches!(event, ProgressEvent::Done);
+                let sse_event = match event {
+                    ProgressEvent::StepStarted(name) =>
+                        sse::Event::default()
+                            .event("step-start")
+                            .json_data(serde_json::json!({ "name": name })),
+                    ProgressEvent::Line { step, stream, line } =>
+                        sse::Event::default()
+                            .event(match stream {
+                                Stream::Stdout => "stdout",
+                                Stream::Stderr => "stderr",
+                            })
+                            .json_data(serde_json::json!({ "step": step, "line": line })),
+                    ProgressEvent::StepFinished(step) =>
+                        sse::Event::default().event("step-finish").json_data(step),
+                    ProgressEvent::Done =>
+                        sse::Event::default().event("done").json_data(jobs.get(id)),
+                };
+                let sse_event = sse_event.unwrap_or_else(|_| sse::Event::default());
+                Some((Ok::<_, Infallible>(sse_event), (replay, receiver, finished)))
+            }
+        }
+    );
+
+    Ok(sse::reply(sse::keep_alive().stream(events)).into_response())
+}
+
+// Function to cancel a job, killing its nargo process and removing its project directory
+async fn cancel_job(id: Uuid, jobs: Arc<JobStore>) -> Result<Json, Rejection> {
+    match jobs.cancel(id).await {
+        Some(job) => Ok(warp::reply::json(&job)),
+        None => Err(ApiError::NotFound("No job found with the given ID".into()).into()),
+    }
+}
+
+// Function to wait for a free worker to run nargo, turning the request away if the queue is full
+async fn acquire_worker(
+    queue: &Arc<JobQueue>,
+    client: Option<SocketAddr>
+) -> Result<Permit, ApiError> {
+    let client = client.map_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED), |addr| addr.ip());
+    queue.acquire(client).await.map_err(ApiError::from)
+}
+
+// Function to tell the caller where their request was in the queue and how long it waited
+fn with_queue_headers(reply: impl Reply, permit: &Permit) -> Response {
+    let mut response = reply.into_response();
+    let headers = response.headers_mut();
+    headers.insert("X-Queue-Position", permit.position.into());
+    headers.insert("X-Queue-Wait-Ms", (permit.waited.as_millis() as u64).into());
+    response
+}
+
+// Function to tell the caller whether the result came from the cache, and under which key
+fn with_cache_headers(
+    reply: impl Reply,
+    key: &str,
+    status: CacheStatus,
+    age: Duration
+) -> Response {
+    let mut response = reply.into_response();
+    let headers = response.headers_mut();
+    let status = match status {
+        CacheStatus::Miss => "miss",
+        CacheStatus::Hit => "hit",
+        CacheStatus::Shared => "shared",
+    };
+    headers.insert("X-Cache", warp::http::HeaderValue::from_static(status));
+    if let Ok(key) = warp::http::HeaderValue::from_str(key) {
+        headers.insert("X-Cache-Key", key);
+    }
+    headers.insert("X-Cache-Age-Ms", (age.as_millis() as u64).into());
+    response
+}
+
+// Function to reply with a result that was already worked out
+fn cached_reply(cached: Cached, key: &str) -> Response {
+    with_cache_headers(warp::reply::json(&cached.value), key, cached.status, cached.age)
+}
+
+// Function to combine code with the challenge's test cases and run it through
+// test, check, prove and verify in a fresh project, followed by the challenge's test vectors.
+// If the same source was compiled before, test and check are taken from artifacts along with
+// the compiled circuit.
+async fn grade_submission(
+    toolchain: &dyn Toolchain,
+    workspaces: &WorkspaceManager,
+    artifacts: Option<&ArtifactCache>,
+    challenge: &challenges::Challenge,
+    code: &str,
+    prover_toml: &str,
+    progress: &Progress
+) -> Result<GradeReport, ApiError> {
+    let workspace = create_workspace(workspaces).await?;
+    let project_dir = workspace.dir();
+    let prover_file_path = project_dir.join("project/Prover.toml");
+
+    // Write the learner's code beside the challenge's hidden tests
+    let sources = Arc::new(write_sources(project_dir, code, &challenge.test_cases).await?);
+    write_helper_modules(project_dir, challenge).await?;
+
+    // Write the Prover inputs to the Prover.toml file
+    tokio::fs
+        ::write(&prover_file_path, prover_toml).await
+        .map_err(|e| {
+            ApiError::Internal(format!("Failed to write to Prover.toml: {}", e))
+        })?;
+
+    // Run the nargo commands to test, prove and verify, then check every test vector
+    let project_sub_dir = workspace.project();
+    let artifact_key = match artifacts {
+        Some(artifacts) => artifacts.key(&project_sub_dir).await.ok(),
+        None => None,
+    };
+    let compiled = match (artifacts, &artifact_key) {
+        (Some(artifacts), Some(key)) => artifacts.restore(key, &project_sub_dir).await,
+        _ => None,
+    };
+    let reused = compiled.as_deref().unwrap_or_default();
+    let steps = run_nargo_commands(toolchain, &project_sub_dir, reused, &sources, progress).await?;
+
+    // Keep the compiled circuit once the source compiles and its tests pass
+    if let (Some(artifacts), Some(key), None) = (artifacts, &artifact_key, &compiled) {
+        let compile_steps: Vec<StepReport> = steps
+            .iter()
+            .filter(|s| s.name == "test" || s.name == "check")
+            .cloned()
+            .collect();
+        if compile_steps.iter().all(|s| s.status == StepStatus::Passed) {
+            if let Err(e) = artifacts.save(key, &project_sub_dir, &compile_steps).await {
+                eprintln!("Failed to save artifact {}: {}", key, e);
+            }
+        }
+    }
+
+    // Test vectors only run once the learner's own inputs prove and verify
+    let vectors = if steps.iter().all(|s| s.status == StepStatus::Passed) {
+        run_vectors(toolchain, &project_sub_dir, &challenge.vectors, &sources).await?
+    } else {
+        vec![]
+    };
+    Ok(GradeReport::new(steps, vectors))
+}
+
+// Function to find the challenge a request refers to
+fn find_challenge(
+    challenges: &[challenges::Challenge],
+    id: u32
+) -> Result<&challenges::Challenge, ApiError> {
+    challenges
+        .iter()
+        .find(|c| c.id == id)
+        .ok_or(ApiError::ChallengeNotFound(id))
+}
+
+// Function to write the learner's code to the project's src/main.nr and the hidden tests to
+// their own module, returning how the files map back to what the learner can see
+async fn write_sources(
+    project_dir: &Path,
+    code: &str,
+    test_cases: &[String]
+) -> Result<SourceMap, ApiError> {
+    let sources = Sources::new(code, test_cases);
+    let src_dir = project_dir.join("project/src");
+    let files = [("main.nr", &sources.main), ("hidden_tests.nr", &sources.hidden_tests)];
+    for (file, contents) in files {
+        tokio::fs
+            ::write(src_dir.join(file), contents).await
+            .map_err(|e| ApiError::Internal(format!("Failed to write to file: {}", e)))?;
+    }
+    Ok(sources.map)
+}
+
+// Function to write a challenge's helper modules into the project's src directory.
+// The server's copy is always used, so learners cannot edit them.
+async fn write_helper_modules(
+    project_dir: &Path,
+    challenge: &challenges::Challenge
+) -> Result<(), ApiError> {
+    for helper in &challenge.helpers {
+        let helper_path = project_dir.join(format!("project/src/{}.nr", helper.name));
+        tokio::fs
+            ::write(&helper_path, &helper.code).await
+            .map_err(|e| {
+                ApiError::Internal(format!("Failed to write to file: {}", e))
+            })?;
+    }
+    Ok(())
+}
+
+// Function to run all nargo commands for executing user submitted code.
+// Every step is reported, including to progress as soon as it is done; once a step fails
+// the remaining steps are skipped. Steps in reused are reported without running them again.
+// Output and diagnostics are redacted with sources, so nothing of the hidden tests is shown.
+async fn run_nargo_commands(
+    toolchain: &dyn Toolchain,
+    dir_buf: &Path,
+    reused: &[StepReport],
+    sources: &Arc<SourceMap>,
+    progress: &Progress
+) -> Result<Vec<StepReport>, ApiError> {
+    let commands = vec![
+        NargoCommand::Test(None),
+        NargoCommand::Check,
+        NargoCommand::Prove("proof-1".to_string()),
+        NargoCommand::Verify("proof-1".to_string())
+    ];
+
+    let mut steps: Vec<StepReport> = vec![];
+    for command in commands {
+        if steps.iter().any(|s| s.status == StepStatus::Failed) {
+            let step = StepReport::skipped(command.name());
+            progress.step(&step);
+            steps.push(step);
+            continue;
+        }
+
+        let name = command.name();
+        if let Some(step) = reused.iter().find(|s| s.name == name) {
+            progress.step(step);
+            steps.push(step.clone());
+            continue;
+        }
+        progress.step_started(name);
+        let started = Instant::now();
+        let on_line = Some(sources.redacting(progress.line_sink()));
+        let output = run_command_streamed(toolchain, command, dir_buf, on_line).await?;
+        let mut step = StepReport::from_output(name, &output, started.elapsed());
+        step.stdout = sources.redact_output(&step.stdout);
+        step.stderr = sources.redact_output(&step.stderr);
+        sources.rewrite(&mut step.diagnostics);
+
+        // Older nargo versions report a failed proof on stdout while still exiting successfully
+        if name == "prove" && step.stdout.contains("FAILED") {
+            step.status = StepStatus::Failed;
+        }
+        progress.step(&step);
+        steps.push(step);
+    }
+
+    Ok(steps)
+}
+
+// Function to turn a rejection into the error's JSON body and HTTP status. Rejections from
+// warp itself, such as a body that is not valid JSON, are mapped onto the matching ApiError.
+async fn handle_rejection(err: warp::Rejection) -> Result<Response, Infallible> {
+    if let Some(e) = err.find::<ApiError>() {
+        return Ok(error_reply(e));
+    }
+    let error = if let Some(e) = err.find::<warp::filters::body::BodyDeserializeError>() {
+        ApiError::BadRequest(e.to_string())
+    } else if let Some(e) = err.find::<warp::reject::InvalidQuery>() {
+        ApiError::BadRequest(e.to_string())
+    } else if let Some(e) = err.find::<warp::reject::PayloadTooLarge>() {
+        ApiError::BadRequest(e.to_string())
+    } else if let Some(e) = err.find::<warp::reject::UnsupportedMediaType>() {
+        ApiError::BadRequest(e.to_string())
+    } else {
+        // Routes check the method before the path, so a wrong method cannot be told apart
+        // from a path nothing is served at
+        ApiError::NotFound("Not found".into())
+    };
+    Ok(error_reply(&error))
+}
+
+// Function to reply with an error's JSON body and status, telling an overloaded caller when
+// to try again
+fn error_reply(error: &ApiError) -> Response {
+    let reply = warp::reply::with_status(warp::reply::json(&error.body()), error.status());
+    let mut response = reply.into_response();
+    if let Some(retry_after) = error.retry_after() {
+        let retry_after = retry_after.as_secs().max(1);
+        response.headers_mut().insert("Retry-After", retry_after.into());
+    }
+    response
+}
@@ -0,0 +1,139 @@
+use crate::challenges::Challenge;
+use crate::workspace::WorkspaceManager;
+use crate::toolchain::{ NargoCommand, Toolchain };
+use crate::{ create_workspace, run_command, write_helper_modules, write_sources };
+use std::collections::HashSet;
+
+// Hidden test functions import what they use from learner code, so they carry a prefix
+// learners are unlikely to use for their own functions
+pub const HIDDEN_FN_PREFIX: &str = "hidden_";
+
+// Function to lint a challenge's hidden test cases without running nargo.
+// Returns a warning message for each problem found.
+pub fn lint_test_cases(challenge: &Challenge) -> Vec<String> {
+    let mut warnings = vec![];
+
+    // Functions the learner is expected to write or already has in their editor
+    let mut user_fns: HashSet<String> = function_names(&challenge.starter_code)
+        .into_iter()
+        .collect();
+    if let Some(solution) = &challenge.solution {
+        user_fns.extend(function_names(&solution.code));
+    }
+    user_fns.insert("main".to_string());
+
+    let mut seen_fns = HashSet::new();
+    for (i, test_case) in challenge.test_cases.iter().enumerate() {
+        let label = format!("test case {}", i + 1);
+        let code = strip_comments(test_case);
+        if !code.contains("#[test") {
+            warnings.push(format!("{}: does not declare a #[test] function", label));
+        }
+        // A test that never calls the learner's code passes whatever they write
+        if !user_fns.iter().any(|name| calls(&code, name)) {
+            let message = "does not call `main` or any other function from the learner's code";
+            warnings.push(format!("{}: {}", label, message));
+        }
+        for name in function_names(test_case) {
+            if user_fns.contains(&name) {
+                warnings.push(format!("{}: function `{}` collides with learner code", label, name));
+            } else if !name.starts_with(HIDDEN_FN_PREFIX) {
+                let prefix = HIDDEN_FN_PREFIX;
+                let message = format!("function `{}` should start with `{}`", name, prefix);
+                warnings.push(format!("{}: {}", label, message));
+            }
+            if !seen_fns.insert(name.clone()) {
+                warnings.push(format!("{}: function `{}` is declared more than once", label, name));
+            }
+        }
+    }
+    warnings
+}
+
+// Function to run nargo check on each hidden test case in isolation, using the challenge's
+// starter code as the stub main. Returns a warning for each test case that fails to compile.
+pub async fn check_test_cases(
+    toolchain: &dyn Toolchain,
+    workspaces: &WorkspaceManager,
+    challenge: &Challenge
+) -> Vec<String> {
+    let mut warnings = vec![];
+    for (i, test_case) in challenge.test_cases.iter().enumerate() {
+        if let Err(message) = check_test_case(toolchain, workspaces, challenge, test_case).await {
+            warnings.push(format!("test case {}: nargo check failed: {}", i + 1, message));
+        }
+    }
+    warnings
+}
+
+async fn check_test_case(
+    toolchain: &dyn Toolchain,
+    workspaces: &WorkspaceManager,
+    challenge: &Challenge,
+    test_case: &str
+) -> Result<(), String> {
+    let workspace = create_workspace(workspaces).await.map_err(|e| e.to_string())?;
+
+    let test_cases = [test_case.to_string()];
+    write_sources(workspace.dir(), &challenge.starter_code, &test_cases).await.map_err(|e| {
+        e.to_string()
+    })?;
+    write_helper_modules(workspace.dir(), challenge).await.map_err(|e| e.to_string())?;
+
+    let output = run_command(toolchain, NargoCommand::Check, &workspace.project()).await.map_err(
+        |e| e.to_string()
+    )?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+// Function to collect the names of functions declared in Noir source
+pub(crate) fn function_names(code: &str) -> Vec<String> {
+    let code = strip_comments(code);
+    let mut names = vec![];
+    let mut rest = code.as_str();
+    while let Some(pos) = rest.find("fn ") {
+        let preceded_by_ident = rest[..pos]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_alphanumeric() || c == '_');
+        rest = &rest[pos + 3..];
+        if preceded_by_ident {
+            continue;
+        }
+        let name: String = rest
+            .trim_start()
+            .chars()
+            .take_while(|c| c.is_alphanumeric() || *c == '_')
+            .collect();
+        if !name.is_empty() {
+            names.push(name);
+        }
+    }
+    names
+}
+
+// Function to check whether code calls the function with the given name
+fn calls(code: &str, name: &str) -> bool {
+    code.match_indices(name).any(|(pos, _)| {
+        let preceded_by_ident = code[..pos]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_alphanumeric() || c == '_');
+        let after = code[pos + name.len()..].trim_start();
+        // Declaring a function of the same name is not calling it
+        let declared = code[..pos].trim_end().ends_with("fn");
+        !preceded_by_ident && !declared && after.starts_with('(')
+    })
+}
+
+// Function to remove line comments so commented-out code is not linted
+fn strip_comments(code: &str) -> String {
+    code.lines()
+        .map(|line| line.split("//").next().unwrap_or(""))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,284 @@
+use crate::sandbox::env_u64;
+use crate::util;
+use serde_derive::Serialize;
+use std::collections::{ HashMap, VecDeque };
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::{ Arc, Mutex, MutexGuard };
+use std::time::{ Duration, Instant };
+use tokio::sync::oneshot;
+
+// Queue that bounds how many nargo jobs run at once. Jobs that cannot start straight away wait
+// in a queue of limited size, and waiting clients take turns so one client submitting many jobs
+// cannot hold up everyone else. Configured with environment variables:
+// - NARGO_WORKERS: jobs run at the same time, defaults to the number of CPUs
+// - NARGO_QUEUE_SIZE: jobs waiting to run before new ones are turned away (default 64)
+// - NARGO_QUEUE_PER_CLIENT: jobs a single client can have waiting, 0 for no limit (default 16)
+pub struct JobQueue {
+    pub workers: usize,
+    pub capacity: usize,
+    pub per_client: usize,
+    state: Mutex<QueueState>,
+}
+
+struct QueueState {
+    running: usize,
+    queued: usize,
+    next_id: u64,
+    // Waiting jobs for each client, and the order in which clients take their turn
+    clients: HashMap<IpAddr, VecDeque<Waiter>>,
+    turns: VecDeque<IpAddr>,
+    // Moving average of how long a job takes, used to suggest when to retry
+    average_duration: Duration,
+}
+
+struct Waiter {
+    id: u64,
+    start: oneshot::Sender<()>,
+}
+
+// Snapshot of the queue returned by GET /queue
+#[derive(Serialize)]
+pub struct QueueStatus {
+    pub workers: usize,
+    pub running: usize,
+    pub queued: usize,
+    pub capacity: usize,
+}
+
+// Error returned when a job is turned away because the queue is full
+#[derive(Debug)]
+pub struct QueueFull {
+    pub message: String,
+    pub retry_after: Duration,
+}
+
+impl fmt::Display for QueueFull {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+// A running job's slot, which is handed to the next waiting job when dropped
+pub struct Permit {
+    queue: Arc<JobQueue>,
+    started: Instant,
+    // Position in the queue when the job was queued, 0 if it started straight away
+    pub position: usize,
+    pub waited: Duration,
+}
+
+impl JobQueue {
+    pub fn from_env() -> Self {
+        let cpus = std::thread::available_parallelism().map_or(1, |n| n.get()) as u64;
+        JobQueue::new(
+            env_u64("NARGO_WORKERS", cpus) as usize,
+            env_u64("NARGO_QUEUE_SIZE", 64) as usize,
+            env_u64("NARGO_QUEUE_PER_CLIENT", 16) as usize
+        )
+    }
+
+    pub fn new(workers: usize, capacity: usize, per_client: usize) -> Self {
+        JobQueue {
+            workers: workers.max(1),
+            capacity,
+            per_client,
+            state: Mutex::new(QueueState {
+                running: 0,
+                queued: 0,
+                next_id: 0,
+                clients: HashMap::new(),
+                turns: VecDeque::new(),
+                average_duration: Duration::from_secs(10),
+            }),
+        }
+    }
+
+    pub fn status(&self) -> QueueStatus {
+        let state = self.lock();
+        QueueStatus {
+            workers: self.workers,
+            running: state.running,
+            queued: state.queued,
+            capacity: self.capacity,
+        }
+    }
+
+    // Function to wait for a slot to run a job for the given client. Fails straight away if the
+    // queue, or the client's share of it, is full.
+    pub async fn acquire(self: &Arc<Self>, client: IpAddr) -> Result<Permit, QueueFull> {
+        Ok(self.enqueue(client)?.wait().await)
+    }
+
+    // Function to queue a job for the given client without waiting for it to start
+    pub fn enqueue(self: &Arc<Self>, client: IpAddr) -> Result<Ticket, QueueFull> {
+        let mut state = self.lock();
+        let starts_now = state.running < self.workers && state.queued == 0;
+        let waiting = state.clients.get(&client).map_or(0, |jobs| jobs.len());
+        if !starts_now && state.queued >= self.capacity {
+            return Err(self.full(&state, "The server is busy, please try again shortly"));
+        }
+        if !starts_now && self.per_client > 0 && waiting >= self.per_client {
+            let message = "You already have too many jobs waiting, please try again shortly";
+            return Err(self.full(&state, message));
+        }
+
+        // Only create the ticket once the job is accepted, as dropping it takes the lock
+        let id = state.next_id;
+        state.next_id += 1;
+        let mut ticket = Ticket {
+            queue: Arc::clone(self),
+            client,
+            id,
+            position: 0,
+            queued_at: Instant::now(),
+            start: None,
+            started: false,
+        };
+        if starts_now {
+            state.running += 1;
+            return Ok(ticket);
+        }
+
+        let (sender, receiver) = oneshot::channel();
+        if waiting == 0 {
+            state.turns.push_back(client);
+        }
+        state.clients.entry(client).or_default().push_back(Waiter { id, start: sender });
+        state.queued += 1;
+        ticket.position = state.position(client, waiting);
+        ticket.start = Some(receiver);
+        Ok(ticket)
+    }
+
+    // Function to get the current 1-based position of a queued job, or None if it is not waiting
+    pub fn position(&self, ticket_id: u64) -> Option<usize> {
+        let state = self.lock();
+        state.clients.iter().find_map(|(client, jobs)| {
+            jobs.iter()
+                .position(|waiter| waiter.id == ticket_id)
+                .map(|index| state.position(*client, index))
+        })
+    }
+
+    fn permit(self: &Arc<Self>, position: usize, waited: Duration) -> Permit {
+        Permit { queue: Arc::clone(self), started: Instant::now(), position, waited }
+    }
+
+    fn full(&self, state: &QueueState, message: &str) -> QueueFull {
+        // Estimate how long it takes for everything queued ahead to run
+        let rounds = (state.queued / self.workers + 1) as u32;
+        QueueFull {
+            message: message.to_string(),
+            retry_after: (state.average_duration * rounds).max(Duration::from_secs(1)),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, QueueState> {
+        util::lock(&self.state)
+    }
+
+    // Function to free a slot and start the next waiting job, taking clients in turn
+    fn release(&self, duration: Option<Duration>) {
+        let mut state = self.lock();
+        if let Some(duration) = duration {
+            state.average_duration = (state.average_duration * 4 + duration) / 5;
+        }
+        state.running -= 1;
+        while state.running < self.workers {
+            let Some(client) = state.turns.pop_front() else {
+                break;
+            };
+            let jobs = state.clients.get_mut(&client).unwrap();
+            let waiter = jobs.pop_front().unwrap();
+            if jobs.is_empty() {
+                state.clients.remove(&client);
+            } else {
+                state.turns.push_back(client);
+            }
+            state.queued -= 1;
+            state.running += 1;
+            if waiter.start.send(()).is_err() {
+                state.running -= 1;
+            }
+        }
+    }
+}
+
+impl QueueState {
+    // Function to work out a waiting job's 1-based position, given its index among the client's
+    // waiting jobs. Each client takes one turn per round, starting from the front of turns.
+    fn position(&self, client: IpAddr, index: usize) -> usize {
+        let mut ahead = index;
+        let mut before = true;
+        for other in &self.turns {
+            if *other == client {
+                before = false;
+                continue;
+            }
+            let waiting = self.clients[other].len();
+            ahead += if before { waiting.min(index + 1) } else { waiting.min(index) };
+        }
+        ahead + 1
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.queue.release(Some(self.started.elapsed()));
+    }
+}
+
+// A queued job. Dropping it before the job starts takes it out of the queue.
+pub struct Ticket {
+    queue: Arc<JobQueue>,
+    client: IpAddr,
+    pub id: u64,
+    // Position in the queue when the job was queued, 0 if it can start straight away
+    pub position: usize,
+    queued_at: Instant,
+    // None if a slot was free when the job was queued
+    start: Option<oneshot::Receiver<()>>,
+    started: bool,
+}
+
+impl Ticket {
+    // Function to wait for the job's turn to run
+    pub async fn wait(mut self) -> Permit {
+        if let Some(start) = self.start.as_mut() {
+            // The sender is only dropped with the queue, in which case run the job anyway
+            let _ = start.await;
+        }
+        self.started = true;
+        self.queue.permit(self.position, self.queued_at.elapsed())
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        if self.started {
+            return;
+        }
+        let mut state = self.queue.lock();
+        let removed = match state.clients.get_mut(&self.client) {
+            Some(jobs) => {
+                let before = jobs.len();
+                jobs.retain(|waiter| waiter.id != self.id);
+                before != jobs.len()
+            }
+            None => false,
+        };
+        if removed {
+            state.queued -= 1;
+            if state.clients[&self.client].is_empty() {
+                state.clients.remove(&self.client);
+                state.turns.retain(|client| *client != self.client);
+            }
+        } else {
+            // The job already holds a slot, either because one was free when it was queued or
+            // because it was given one just as it was dropped, so pass the slot on
+            drop(state);
+            self.queue.release(None);
+        }
+    }
+}
@@ -0,0 +1,263 @@
+use crate::diagnostics::{ self, Diagnostic };
+use crate::vectors::VectorResult;
+use serde_derive::{ Deserialize, Serialize };
+use std::process::Output;
+use std::time::Duration;
+
+// Grading report returned by POST /execute
+#[derive(Serialize)]
+pub struct GradeReport {
+    pub verdict: Verdict,
+    pub message: String,
+    pub steps: Vec<StepReport>,
+    pub vectors: Vec<VectorResult>,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Verdict {
+    Passed,
+    Failed,
+}
+
+// Outcome of a single nargo step
+#[derive(Serialize, Deserialize, Clone)]
+pub struct StepReport {
+    pub name: String,
+    pub status: StepStatus,
+    pub duration_ms: u64,
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    // Errors and warnings nargo reported about the code
+    #[serde(default)]
+    pub diagnostics: Vec<Diagnostic>,
+    // Whether the step was reused from an earlier compilation of the same source
+    #[serde(default)]
+    pub cached: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum StepStatus {
+    Passed,
+    Failed,
+    Skipped,
+}
+
+impl GradeReport {
+    // Function to build the report, working out the verdict from the steps and vectors
+    pub fn new(steps: Vec<StepReport>, vectors: Vec<VectorResult>) -> Self {
+        let failed_step = steps.iter().find(|s| s.status == StepStatus::Failed);
+        let failed = vectors
+            .iter()
+            .filter(|v| !v.passed)
+            .count();
+
+        let (verdict, message) = match failed_step {
+            Some(step) => (Verdict::Failed, format!("Failed at step `{}`", step.name)),
+            None if failed > 0 => {
+                (Verdict::Failed, format!("{} of {} test vectors failed", failed, vectors.len()))
+            }
+            None => (Verdict::Passed, "Success!".to_string()),
+        };
+
+        GradeReport { verdict, message, steps, vectors }
+    }
+}
+
+impl StepReport {
+    // Function to record a step that ran, passing if the command exited successfully
+    pub fn from_output(name: &str, output: &Output, duration: Duration) -> Self {
+        let status = if output.status.success() { StepStatus::Passed } else { StepStatus::Failed };
+        let stderr = strip_ansi(&output.stderr);
+        StepReport {
+            name: name.to_string(),
+            status,
+            duration_ms: duration.as_millis() as u64,
+            exit_code: output.status.code(),
+            stdout: strip_ansi(&output.stdout),
+            diagnostics: diagnostics::parse(&stderr),
+            stderr,
+            cached: false,
+        }
+    }
+
+    // Function to record a step that did not run because an earlier step failed
+    pub fn skipped(name: &str) -> Self {
+        StepReport {
+            name: name.to_string(),
+            status: StepStatus::Skipped,
+            duration_ms: 0,
+            exit_code: None,
+            stdout: String::new(),
+            stderr: String::new(),
+            diagnostics: vec![],
+            cached: false,
+        }
+    }
+}
+
+// Function to decode command output with terminal colour codes removed
+pub fn strip_ansi(bytes: &[u8]) -> String {
+    let stripped = strip_ansi_escapes::strip(bytes).unwrap_or_else(|_| bytes.to_vec());
+    String::from_utf8_lossy(&stripped).trim().to_string()
+}
+
+// Test results returned by POST /execute_test
+#[derive(Serialize)]
+pub struct TestReport {
+    pub passed: bool,
+    pub message: String,
+    pub tests: Vec<TestResult>,
+    pub stdout: String,
+    pub stderr: String,
+    // Errors and warnings nargo reported while compiling the tests
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+// Outcome of a single test function, with anything it printed via std::println
+#[derive(Serialize)]
+pub struct TestResult {
+    pub name: String,
+    pub status: StepStatus,
+    pub failure_message: Option<String>,
+    pub output: Vec<String>,
+}
+
+impl TestReport {
+    // Function to build the report for a run where no tests were executed
+    pub fn empty(message: &str) -> Self {
+        TestReport {
+            passed: false,
+            message: message.to_string(),
+            tests: vec![],
+            stdout: String::new(),
+            stderr: String::new(),
+            diagnostics: vec![],
+        }
+    }
+
+    // Function to build the report from the output of nargo test
+    pub fn from_output(output: &Output) -> Self {
+        let stdout = strip_ansi(&output.stdout);
+        let stderr = strip_ansi(&output.stderr);
+        let tests = parse_test_output(&stdout, &stderr, output.status.success());
+
+        let failed = tests
+            .iter()
+            .filter(|t| t.status == StepStatus::Failed)
+            .count();
+        let passed = output.status.success() && failed == 0;
+        let message = if tests.is_empty() && !passed {
+            // Nothing ran, so the code most likely failed to compile
+            "Tests could not be run".to_string()
+        } else if tests.is_empty() {
+            "No tests were run".to_string()
+        } else if failed == 0 && passed {
+            format!("{} of {} tests passed", tests.len(), tests.len())
+        } else {
+            format!("{} of {} tests failed", failed, tests.len())
+        };
+
+        let diagnostics = diagnostics::parse(&stderr);
+        TestReport { passed, message, tests, stdout, stderr, diagnostics }
+    }
+}
+
+// Function to parse nargo test output. Each test starts with a `Testing <name>...` line and
+// ends with `ok` or `FAIL`, either on the same line or after any lines the test printed.
+// Failure messages are written to stderr, so they are matched to failed tests in order.
+fn parse_test_output(stdout: &str, stderr: &str, success: bool) -> Vec<TestResult> {
+    let mut tests: Vec<TestResult> = vec![];
+    let mut running = false;
+
+    for line in stdout.lines() {
+        let line = strip_package_prefix(line);
+        if let Some(rest) = line.strip_prefix("Testing ") {
+            let (name, verdict) = match rest.split_once("...") {
+                Some((name, verdict)) => (name.trim(), verdict.trim()),
+                None => (rest.trim(), ""),
+            };
+            tests.push(TestResult {
+                name: name.to_string(),
+                status: StepStatus::Skipped,
+                failure_message: None,
+                output: vec![],
+            });
+            running = true;
+            if let Some(status) = parse_verdict(verdict) {
+                tests.last_mut().unwrap().status = status;
+                running = false;
+            }
+        } else if running {
+            let test = tests.last_mut().unwrap();
+            match parse_verdict(line.trim()) {
+                Some(status) => {
+                    test.status = status;
+                    running = false;
+                }
+                None => test.output.push(line.to_string()),
+            }
+        }
+    }
+
+    // A test that never reported a verdict stopped the run, so it failed if nargo did
+    for test in tests.iter_mut().filter(|t| t.status == StepStatus::Skipped) {
+        test.status = if success { StepStatus::Passed } else { StepStatus::Failed };
+    }
+
+    let errors = split_errors(stderr);
+    let mut failed: Vec<&mut TestResult> = tests
+        .iter_mut()
+        .filter(|t| t.status == StepStatus::Failed)
+        .collect();
+    if failed.len() == errors.len() {
+        for (test, error) in failed.iter_mut().zip(errors) {
+            test.failure_message = Some(error);
+        }
+    } else if !stderr.is_empty() {
+        for test in failed {
+            test.failure_message = Some(stderr.to_string());
+        }
+    }
+
+    tests
+}
+
+fn parse_verdict(verdict: &str) -> Option<StepStatus> {
+    match verdict {
+        "ok" => Some(StepStatus::Passed),
+        "FAIL" | "FAILED" | "fail" => Some(StepStatus::Failed),
+        _ => None,
+    }
+}
+
+// Function to remove the `[package] ` prefix newer nargo versions put on each line
+fn strip_package_prefix(line: &str) -> &str {
+    if line.starts_with('[') {
+        if let Some((_, rest)) = line.split_once("] ") {
+            return rest;
+        }
+    }
+    line
+}
+
+// Function to split stderr into one block per reported error
+fn split_errors(stderr: &str) -> Vec<String> {
+    let mut errors: Vec<String> = vec![];
+    for line in stderr.lines() {
+        if line.starts_with("error") || errors.is_empty() {
+            errors.push(line.to_string());
+        } else {
+            let error = errors.last_mut().unwrap();
+            error.push('\n');
+            error.push_str(line);
+        }
+    }
+    errors
+        .into_iter()
+        .map(|e| e.trim().to_string())
+        .filter(|e| !e.is_empty())
+        .collect()
+}
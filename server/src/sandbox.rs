@@ -0,0 +1,383 @@
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::fmt;
+use std::io;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{ Output, Stdio };
+use std::sync::{ Arc, OnceLock };
+use std::time::Duration;
+use serde_derive::Serialize;
+use tokio::io::{ AsyncRead, AsyncReadExt };
+use tokio::process::Command;
+use tokio::task;
+
+use crate::cgroups::RunCgroup;
+use crate::isolation;
+
+// Resource limits applied to every nargo process. Configured with environment variables,
+// where 0 disables a limit.
+#[derive(Debug)]
+pub struct Limits {
+    // NARGO_CPU_SECONDS: CPU time, enforced with RLIMIT_CPU
+    pub cpu_seconds: u64,
+    // NARGO_MEMORY_MB: address space, enforced with RLIMIT_AS
+    pub memory_bytes: u64,
+    // NARGO_MAX_PROCESSES: processes and threads of a single run, enforced with a cgroup of
+    // its own
+    pub max_processes: u64,
+    // NARGO_MAX_FILE_MB: size of any file written, enforced with RLIMIT_FSIZE
+    pub max_file_bytes: u64,
+    // NARGO_TIMEOUT_SECONDS: wall-clock time before the whole process group is killed
+    pub wall_clock: Duration,
+    // NARGO_MAX_OUTPUT_KB: stdout and stderr kept per stream, the rest is dropped
+    pub max_output_bytes: usize,
+}
+
+static LIMITS: OnceLock<Limits> = OnceLock::new();
+
+impl Limits {
+    // Function to get the limits configured for this server
+    pub fn get() -> &'static Limits {
+        LIMITS.get_or_init(Limits::from_env)
+    }
+
+    fn from_env() -> Self {
+        const MB: u64 = 1024 * 1024;
+        Limits {
+            cpu_seconds: env_u64("NARGO_CPU_SECONDS", 60),
+            memory_bytes: env_u64("NARGO_MEMORY_MB", 8192) * MB,
+            max_processes: env_u64("NARGO_MAX_PROCESSES", 256),
+            max_file_bytes: env_u64("NARGO_MAX_FILE_MB", 256) * MB,
+            wall_clock: Duration::from_secs(env_u64("NARGO_TIMEOUT_SECONDS", 120)),
+            max_output_bytes: (env_u64("NARGO_MAX_OUTPUT_KB", 1024) * 1024) as usize,
+        }
+    }
+}
+
+pub fn env_u64(name: &str, default: u64) -> u64 {
+    match std::env::var(name) {
+        Ok(value) => value.parse().unwrap_or_else(|_| panic!("{} must be a number.", name)),
+        Err(_) => default,
+    }
+}
+
+// Which limit a process ran into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitKind {
+    CpuTime,
+    Memory,
+    FileSize,
+    WallClock,
+    Processes,
+    // Killed by a signal the server did not send, with no sign of why
+    Killed,
+}
+
+// Error returned when a nargo process was stopped for exceeding a limit
+#[derive(Debug)]
+pub struct LimitExceeded {
+    pub kind: LimitKind,
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let limits = Limits::get();
+        match self.kind {
+            LimitKind::CpuTime =>
+                write!(
+                    f,
+                    "Your code used more than {} seconds of CPU time and was stopped",
+                    limits.cpu_seconds
+                ),
+            LimitKind::Memory =>
+                write!(
+                    f,
+                    "Your code needed more than {} MB of memory and was stopped",
+                    limits.memory_bytes / (1024 * 1024)
+                ),
+            LimitKind::FileSize =>
+                write!(
+                    f,
+                    "Your code tried to write a file larger than {} MB and was stopped",
+                    limits.max_file_bytes / (1024 * 1024)
+                ),
+            LimitKind::WallClock =>
+                write!(
+                    f,
+                    "Your code took longer than {} seconds to run and was stopped",
+                    limits.wall_clock.as_secs()
+                ),
+            LimitKind::Processes =>
+                write!(
+                    f,
+                    "Your code tried to run more than {} processes or threads and was stopped",
+                    limits.max_processes
+                ),
+            LimitKind::Killed => write!(f, "nargo was killed before it finished"),
+        }
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+// Which output stream a line was written to
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+// Callback given each line of output as soon as the process writes it
+pub type LineSink = Arc<dyn Fn(Stream, &str) + Send + Sync>;
+
+#[derive(Debug)]
+pub enum RunError {
+    Io(io::Error),
+    Limit(LimitExceeded),
+}
+
+impl From<io::Error> for RunError {
+    fn from(e: io::Error) -> Self {
+        RunError::Io(e)
+    }
+}
+
+// Function to run a command to completion under the configured limits and isolation. The
+// command runs in its own process group so that it and anything it spawns can be killed
+// together, which also happens if the returned future is dropped before the command ends.
+// Each line of output is also passed to on_line as it is written.
+pub async fn run_limited(
+    mut cmd_obj: Command,
+    on_line: Option<LineSink>
+) -> Result<Output, RunError> {
+    let limits = Limits::get();
+    let work_dir = match cmd_obj.as_std().get_current_dir() {
+        Some(dir) => dir.to_path_buf(),
+        None => std::env::current_dir()?,
+    };
+    // Reading mount points and making the cgroup touch the filesystem, so they must not hold up
+    // the executor
+    let max_processes = limits.max_processes;
+    let (isolation, cgroup, oom_kills) = task
+        ::spawn_blocking(move || -> io::Result<_> {
+            let isolation = isolation::prepare(&work_dir)?;
+            let cgroup = RunCgroup::create(max_processes)?.map(Arc::new);
+            Ok((isolation, cgroup, system_oom_kills()))
+        }).await
+        .map_err(io::Error::other)??;
+    let child_cgroup = cgroup.clone();
+    let cpu_seconds = limits.cpu_seconds;
+    let memory_bytes = limits.memory_bytes;
+    let max_file_bytes = limits.max_file_bytes;
+
+    cmd_obj.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped());
+    cmd_obj.kill_on_drop(true);
+    // Safety: only async-signal-safe libc calls are made between fork and exec
+    unsafe {
+        cmd_obj.pre_exec(move || {
+            if libc::setpgid(0, 0) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // Join the run's cgroup before leaving the server's user namespace
+            if let Some(cgroup) = &child_cgroup {
+                cgroup.join()?;
+            }
+            // The soft CPU limit sends SIGXCPU, the hard limit a second later sends SIGKILL
+            set_limit(libc::RLIMIT_CPU, cpu_seconds, cpu_seconds + 1)?;
+            set_limit(libc::RLIMIT_AS, memory_bytes, memory_bytes)?;
+            set_limit(libc::RLIMIT_FSIZE, max_file_bytes, max_file_bytes)?;
+            if let Some(isolation) = &isolation {
+                isolation.apply()?;
+            }
+            Ok(())
+        });
+    }
+
+    let mut child = cmd_obj.spawn()?;
+    let group = match child.id() {
+        Some(pid) => KillGroupOnDrop { pgid: pid as libc::pid_t, ended: AtomicBool::new(false) },
+        None => {
+            return Err(io::Error::other("Process exited before it could be tracked").into());
+        }
+    };
+
+    let max_output = limits.max_output_bytes;
+    let stdout = read_capped(child.stdout.take(), max_output, Stream::Stdout, on_line.clone());
+    let stderr = read_capped(child.stderr.take(), max_output, Stream::Stderr, on_line);
+    let wait = async {
+        let result = if limits.wall_clock.is_zero() {
+            child.wait().await.map(|status| (status, false))
+        } else {
+            match tokio::time::timeout(limits.wall_clock, child.wait()).await {
+                Ok(status) => status.map(|status| (status, false)),
+                Err(_) => {
+                    group.kill();
+                    child.wait().await.map(|status| (status, true))
+                }
+            }
+        };
+        // Kill anything the process left behind, so nothing keeps running or holds the pipes
+        // open
+        group.kill();
+        group.ended.store(true, Ordering::Relaxed);
+        result
+    };
+    let (result, stdout, stderr) = tokio::join!(wait, stdout, stderr);
+    let (status, timed_out) = result?;
+
+    // The command holds the other reference to the cgroup, which is removed once both are gone
+    drop(cmd_obj);
+    let (hit_process_limit, oom_killed) = task
+        ::spawn_blocking(move || {
+            let hit_process_limit = cgroup.as_ref().is_some_and(|cgroup| {
+                cgroup.hit_process_limit()
+            });
+            let oom_killed = match cgroup.as_ref().and_then(|cgroup| cgroup.oom_killed()) {
+                Some(oom_killed) => oom_killed,
+                None => system_oom_kills() > oom_kills,
+            };
+            (hit_process_limit, oom_killed)
+        }).await
+        .map_err(io::Error::other)?;
+    let ended = Ended {
+        timed_out,
+        succeeded: status.success(),
+        signal: status.signal(),
+        hit_process_limit,
+        oom_killed,
+    };
+    if let Some(kind) = exceeded_limit(&ended, &stderr) {
+        return Err(RunError::Limit(LimitExceeded { kind }));
+    }
+
+    Ok(Output { status, stdout, stderr })
+}
+
+// Process group that is killed when dropped, so a command does not outlive a caller that
+// stopped waiting for it
+struct KillGroupOnDrop {
+    pgid: libc::pid_t,
+    // Set once the group has been killed after the command ended, as its ID may then be reused
+    ended: AtomicBool,
+}
+
+impl KillGroupOnDrop {
+    fn kill(&self) {
+        unsafe {
+            libc::killpg(self.pgid, libc::SIGKILL);
+        }
+    }
+}
+
+impl Drop for KillGroupOnDrop {
+    fn drop(&mut self) {
+        if !self.ended.load(Ordering::Relaxed) {
+            self.kill();
+        }
+    }
+}
+
+// How a process ended, as far as the limits are concerned
+struct Ended {
+    timed_out: bool,
+    succeeded: bool,
+    signal: Option<i32>,
+    // Whether the run's cgroup refused to start a process or thread
+    hit_process_limit: bool,
+    // Whether the kernel's OOM killer killed a process while the command ran
+    oom_killed: bool,
+}
+
+// Function to work out whether a process was stopped because it hit a limit
+fn exceeded_limit(ended: &Ended, stderr: &[u8]) -> Option<LimitKind> {
+    if ended.timed_out {
+        return Some(LimitKind::WallClock);
+    }
+    let kind = match ended.signal {
+        Some(libc::SIGXCPU) => Some(LimitKind::CpuTime),
+        Some(libc::SIGXFSZ) => Some(LimitKind::FileSize),
+        // We only send SIGKILL on timeout, so it came from the OOM killer or from outside
+        Some(libc::SIGKILL) if ended.oom_killed => Some(LimitKind::Memory),
+        Some(libc::SIGKILL) if ended.hit_process_limit => Some(LimitKind::Processes),
+        Some(libc::SIGKILL) => Some(LimitKind::Killed),
+        // Rust programs abort with this message when an allocation is refused
+        Some(libc::SIGABRT) if String::from_utf8_lossy(stderr).contains("memory allocation") => {
+            Some(LimitKind::Memory)
+        }
+        _ => None,
+    };
+    // Being refused a process does not stop nargo, but it usually fails because of it
+    kind.or((ended.hit_process_limit && !ended.succeeded).then_some(LimitKind::Processes))
+}
+
+// Function to read how many processes the kernel's OOM killer has killed since boot
+fn system_oom_kills() -> u64 {
+    std::fs
+        ::read_to_string("/proc/vmstat")
+        .unwrap_or_default()
+        .lines()
+        .find_map(|line| line.strip_prefix("oom_kill "))
+        .and_then(|count| count.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+// glibc declares setrlimit with a resource type of its own, other C libraries with an int
+#[cfg(target_env = "gnu")]
+type Resource = libc::__rlimit_resource_t;
+#[cfg(not(target_env = "gnu"))]
+type Resource = libc::c_int;
+
+fn set_limit(resource: Resource, soft: u64, hard: u64) -> io::Result<()> {
+    if soft == 0 {
+        return Ok(());
+    }
+    let limit = libc::rlimit { rlim_cur: soft as libc::rlim_t, rlim_max: hard as libc::rlim_t };
+    if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// Function to read a pipe to the end, keeping at most max bytes. Lines within the kept bytes
+// are passed to on_line as they arrive.
+async fn read_capped(
+    pipe: Option<impl AsyncRead + Unpin>,
+    max: usize,
+    stream: Stream,
+    on_line: Option<LineSink>
+) -> Vec<u8> {
+    let mut kept = vec![];
+    let Some(mut pipe) = pipe else {
+        return kept;
+    };
+    let mut buf = vec![0u8; 8192];
+    let mut truncated = false;
+    // Start of the line that has not been passed to on_line yet
+    let mut line_start = 0;
+    loop {
+        match pipe.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => {
+                let room = if max == 0 { n } else { max.saturating_sub(kept.len()) };
+                kept.extend_from_slice(&buf[..n.min(room)]);
+                truncated |= n > room;
+            }
+        }
+        if let Some(on_line) = &on_line {
+            while let Some(end) = kept[line_start..].iter().position(|b| *b == b'\n') {
+                let line = &kept[line_start..line_start + end];
+                on_line(stream, &String::from_utf8_lossy(line));
+                line_start += end + 1;
+            }
+        }
+    }
+    if let Some(on_line) = &on_line {
+        if line_start < kept.len() {
+            on_line(stream, &String::from_utf8_lossy(&kept[line_start..]));
+        }
+    }
+    if truncated {
+        kept.extend_from_slice(b"\n... output truncated");
+    }
+    kept
+}
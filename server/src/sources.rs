@@ -0,0 +1,306 @@
+use crate::diagnostics::{ self, Diagnostic, Severity };
+use crate::lint::{ function_names, HIDDEN_FN_PREFIX };
+use crate::sandbox::{ LineSink, Stream };
+use crate::util::lock;
+use std::sync::{ Arc, Mutex };
+
+pub const MAIN_FILE: &str = "src/main.nr";
+pub const HIDDEN_MODULE: &str = "hidden_tests";
+pub const HIDDEN_FILE: &str = "src/hidden_tests.nr";
+
+// How the files of a generated project map back to what the learner can see. The learner's
+// code is src/main.nr, line for line, followed by a line declaring the hidden tests' module;
+// the hidden tests are in src/hidden_tests.nr. Helper modules are shown to learners as they are.
+#[derive(Clone)]
+pub struct SourceMap {
+    // Lines of src/main.nr from this one on were added by the server
+    first_generated_line: usize,
+    // The line of src/hidden_tests.nr each hidden test starts on, with the test's name
+    hidden_tests: Vec<(usize, String)>,
+}
+
+// Where a line of a generated file came from
+#[derive(Debug, PartialEq, Eq)]
+pub enum Origin<'a> {
+    // Code the learner wrote or was given
+    Visible,
+    // A line the server added to tie the project together
+    Generated,
+    // A hidden test, by name
+    HiddenTest(&'a str),
+}
+
+// The source files of a generated project
+pub struct Sources {
+    pub main: String,
+    pub hidden_tests: String,
+    pub map: SourceMap,
+}
+
+impl Sources {
+    // Function to lay out the learner's code and a challenge's hidden tests as a project. The
+    // hidden tests import whatever they use from the learner's code.
+    pub fn new(code: &str, test_cases: &[String]) -> Self {
+        let main = format!("{}\nmod {};\n", code, HIDDEN_MODULE);
+        let first_generated_line = code.matches('\n').count() + 2;
+
+        let tests = test_cases.join("\n");
+        let imports: Vec<String> = item_names(code)
+            .into_iter()
+            .filter(|name| mentions(&tests, name))
+            .collect();
+        let mut hidden_tests = String::new();
+        if mentions(&tests, "std") {
+            hidden_tests.push_str("use dep::std;\n");
+        }
+        if !imports.is_empty() {
+            hidden_tests.push_str(&format!("use crate::{{ {} }};\n\n", imports.join(", ")));
+        }
+
+        let mut starts = vec![];
+        for (i, test_case) in test_cases.iter().enumerate() {
+            let line = hidden_tests.matches('\n').count() + 1;
+            let name = function_names(test_case)
+                .into_iter()
+                .find(|name| name.starts_with(HIDDEN_FN_PREFIX))
+                .unwrap_or_else(|| format!("test case {}", i + 1));
+            starts.push((line, name));
+            hidden_tests.push_str(test_case);
+            hidden_tests.push('\n');
+        }
+
+        let map = SourceMap { first_generated_line, hidden_tests: starts };
+        Sources { main, hidden_tests, map }
+    }
+}
+
+impl SourceMap {
+    // Function to find where a line of a generated file came from
+    pub fn origin(&self, file: &str, line: usize) -> Origin<'_> {
+        if file == MAIN_FILE && line >= self.first_generated_line {
+            return Origin::Generated;
+        }
+        if file != HIDDEN_FILE {
+            return Origin::Visible;
+        }
+        match self.hidden_tests.iter().rev().find(|(start, _)| *start <= line) {
+            Some((_, name)) => Origin::HiddenTest(name),
+            None => Origin::Generated,
+        }
+    }
+
+    // Function to rewrite diagnostics so they only point at lines the learner can see. An error
+    // inside a hidden test is reported as that test failing, without its location; warnings
+    // about hidden code are dropped, since the learner cannot act on them.
+    pub fn rewrite(&self, diagnostics: &mut Vec<Diagnostic>) {
+        diagnostics.retain(|diagnostic| {
+            diagnostic.severity == Severity::Error || self.located_in_view(diagnostic)
+        });
+        for diagnostic in diagnostics.iter_mut() {
+            if let (Some(file), Some(line)) = (&diagnostic.file, diagnostic.line) {
+                match self.origin(file, line) {
+                    Origin::Visible => {}
+                    origin => {
+                        diagnostic.message = self.hidden_message(&origin, &diagnostic.message);
+                        diagnostic.file = None;
+                        diagnostic.line = None;
+                        diagnostic.column = None;
+                        diagnostic.length = None;
+                    }
+                }
+            }
+            diagnostic.labels.retain(|label| {
+                self.origin(&label.file, label.line) == Origin::Visible
+            });
+            for note in &mut diagnostic.notes {
+                *note = note
+                    .lines()
+                    .map(|line| self.redact_reference(line))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+            }
+        }
+    }
+
+    // Function to remove the hidden tests' code from nargo's output, keeping the rest. Source
+    // lines nargo quotes from hidden code, and the underlines beneath them, are left out.
+    pub fn redact_output(&self, output: &str) -> String {
+        let mut filter = OutputFilter::default();
+        let mut lines: Vec<String> = output
+            .lines()
+            .flat_map(|line| filter.line(self, line))
+            .collect();
+        lines.extend(filter.finish());
+        lines.join("\n")
+    }
+
+    // Function to wrap a sink so the lines streamed to it are redacted like redact_output
+    pub fn redacting(self: &Arc<Self>, sink: LineSink) -> LineSink {
+        let map = Arc::clone(self);
+        let filters = Mutex::new((OutputFilter::default(), OutputFilter::default()));
+        Arc::new(move |stream, line| {
+            // Lines arrive with terminal colour codes, which would hide their layout
+            let stripped = strip_ansi_escapes::strip(line).unwrap_or_else(|_| line.into());
+            let stripped = String::from_utf8_lossy(&stripped);
+            let mut filters = lock(&filters);
+            let filter = match stream {
+                Stream::Stdout => &mut filters.0,
+                Stream::Stderr => &mut filters.1,
+            };
+            for line in filter.line(&map, stripped.trim_end()) {
+                sink(stream, &line);
+            }
+        })
+    }
+
+    fn located_in_view(&self, diagnostic: &Diagnostic) -> bool {
+        match (&diagnostic.file, diagnostic.line) {
+            (Some(file), Some(line)) => self.origin(file, line) == Origin::Visible,
+            _ => true,
+        }
+    }
+
+    fn hidden_message(&self, origin: &Origin, message: &str) -> String {
+        match origin {
+            Origin::HiddenTest(name) => format!("hidden test `{}` failed: {}", name, message),
+            _ => format!("hidden tests failed: {}", message),
+        }
+    }
+
+    // Function to replace a line mentioning a location in hidden code, such as an entry of a
+    // call stack, with the name of the hidden test
+    fn redact_reference(&self, line: &str) -> String {
+        for file in [HIDDEN_FILE, MAIN_FILE] {
+            let Some(start) = line.find(file) else {
+                continue;
+            };
+            let rest = &line[start + file.len()..];
+            let number: String = rest
+                .strip_prefix(':')
+                .unwrap_or_default()
+                .chars()
+                .take_while(char::is_ascii_digit)
+                .collect();
+            let Ok(number) = number.parse() else {
+                continue;
+            };
+            // Paths may be absolute, so the whole path is replaced
+            let before = &line[..start];
+            let before = &before[..before.rfind(' ').map_or(0, |i| i + 1)];
+            match self.origin(file, number) {
+                Origin::Visible => {}
+                Origin::HiddenTest(name) => {
+                    return format!("{}hidden test `{}`", before, name);
+                }
+                Origin::Generated => {
+                    return format!("{}hidden tests", before);
+                }
+            }
+        }
+        line.to_string()
+    }
+}
+
+// Redacts nargo's output line by line, remembering which file the lines being quoted are from
+#[derive(Default)]
+struct OutputFilter {
+    file: String,
+    line: usize,
+    // A warning's first line, held back until it is known whether the warning is about hidden
+    // code
+    warning: Option<String>,
+    // Whether the lines of a warning about hidden code are being left out
+    skipping: bool,
+}
+
+impl OutputFilter {
+    // Function to get the lines to show in place of a line, which may be none
+    fn line(&mut self, map: &SourceMap, line: &str) -> Vec<String> {
+        let mut lines: Vec<String> = self.warning.take().into_iter().collect();
+        if let Some((file, number, _)) = diagnostics::location(line) {
+            self.file = file;
+            self.line = number;
+            if map.origin(&self.file, number) == Origin::Visible {
+                lines.push(line.to_string());
+            } else if self.skipping || !lines.is_empty() {
+                // A warning about hidden code, which is left out until the blank line ending it
+                lines.clear();
+                self.skipping = true;
+            } else {
+                lines.push(map.redact_reference(line));
+            }
+            return lines;
+        }
+
+        if let Some(severity) = diagnostics::header_severity(line) {
+            self.file.clear();
+            self.skipping = false;
+            if severity == Severity::Warning {
+                self.warning = Some(line.to_string());
+            } else {
+                lines.push(line.to_string());
+            }
+            return lines;
+        }
+        if self.skipping {
+            self.skipping = !line.trim().is_empty();
+            return lines;
+        }
+
+        if let Some(number) = diagnostics::source_line_number(line) {
+            self.line = number;
+        } else if diagnostics::gutter(line).is_none() {
+            lines.push(map.redact_reference(line));
+            return lines;
+        }
+        // A quoted source line or the underlines and labels beneath it
+        if self.file.is_empty() || map.origin(&self.file, self.line) == Origin::Visible {
+            lines.push(line.to_string());
+        }
+        lines
+    }
+
+    // Function to get a line still held back once the output has ended
+    fn finish(&mut self) -> Option<String> {
+        self.warning.take()
+    }
+}
+
+// Function to list the names of the items declared at the top level of code, which the hidden
+// tests may use
+fn item_names(code: &str) -> Vec<String> {
+    let mut names = vec![];
+    let mut depth = 0i32;
+    for line in code.lines() {
+        let line = line.split("//").next().unwrap_or_default();
+        if depth == 0 {
+            let declaration = line.trim_start().trim_start_matches("pub ").trim_start();
+            for keyword in ["fn ", "struct ", "global ", "mod ", "trait ", "type "] {
+                if let Some(rest) = declaration.strip_prefix(keyword) {
+                    let name: String = rest
+                        .trim_start()
+                        .chars()
+                        .take_while(|c| c.is_alphanumeric() || *c == '_')
+                        .collect();
+                    if !name.is_empty() && name != HIDDEN_MODULE {
+                        names.push(name);
+                    }
+                }
+            }
+        }
+        depth += line.matches('{').count() as i32;
+        depth -= line.matches('}').count() as i32;
+    }
+    names.dedup();
+    names
+}
+
+// Function to check whether code uses a name as a whole word
+fn mentions(code: &str, name: &str) -> bool {
+    code.match_indices(name).any(|(i, _)| {
+        let before = code[..i].chars().next_back();
+        let after = code[i + name.len()..].chars().next();
+        let is_ident = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+        !is_ident(before) && !is_ident(after)
+    })
+}
@@ -0,0 +1,75 @@
+use crate::sandbox::RunError;
+use crate::toolchain::{ NargoCommand, Toolchain };
+use std::io;
+use std::path::{ Path, PathBuf };
+use uuid::Uuid;
+
+// A Noir project created once at startup with `nargo new`, which is copied into each request's
+// project directory instead of running `nargo new` for every request. The template is removed
+// when it is dropped.
+pub struct ProjectTemplate {
+    // Directory holding the template project in its `project` sub-directory
+    dir: PathBuf,
+}
+
+impl ProjectTemplate {
+    // Function to create the template in a new directory under root
+    pub async fn create(toolchain: &dyn Toolchain, root: &Path) -> Result<Self, String> {
+        let dir = root.join(format!("template-{}", Uuid::new_v4()));
+        tokio::fs
+            ::create_dir_all(&dir).await
+            .map_err(|e| format!("Failed to create dir {}: {}", dir.display(), e))?;
+        // Built before running nargo so that the directory is removed if nargo fails
+        let template = ProjectTemplate { dir };
+
+        let command = NargoCommand::New("project".to_string());
+        let output = match toolchain.run(&template.dir, command, None).await {
+            Ok(output) => output,
+            Err(RunError::Io(e)) => {
+                return Err(format!("Failed to run nargo new: {}", e));
+            }
+            Err(RunError::Limit(e)) => {
+                return Err(format!("Failed to run nargo new: {}", e));
+            }
+        };
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(format!("nargo new failed: {}", stderr.trim()));
+        }
+        Ok(template)
+    }
+
+    // Function to get the directory holding the template
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    // Function to copy the template project into project_dir, as its `project` sub-directory
+    pub async fn copy_to(&self, project_dir: &Path) -> io::Result<()> {
+        copy_dir(&self.dir.join("project"), &project_dir.join("project")).await
+    }
+}
+
+impl Drop for ProjectTemplate {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+// Function to copy a directory and everything in it
+pub(crate) async fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    let mut pending = vec![(from.to_path_buf(), to.to_path_buf())];
+    while let Some((from, to)) = pending.pop() {
+        tokio::fs::create_dir(&to).await?;
+        let mut entries = tokio::fs::read_dir(&from).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let target = to.join(entry.file_name());
+            if entry.file_type().await?.is_dir() {
+                pending.push((entry.path(), target));
+            } else {
+                tokio::fs::copy(entry.path(), target).await?;
+            }
+        }
+    }
+    Ok(())
+}
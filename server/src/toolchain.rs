@@ -0,0 +1,107 @@
+use crate::sandbox::{ self, LineSink, RunError };
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::process::Output;
+use tokio::process::Command;
+
+// A nargo command the server runs in a project directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NargoCommand {
+    // Create a new project in a sub-directory with the given name
+    New(String),
+    Check,
+    // Run the project's tests, only those whose name contains the filter if one is given
+    Test(Option<String>),
+    Execute,
+    // Prove with the inputs in Prover.toml, saving the proof under the given name
+    Prove(String),
+    // Verify the proof saved under the given name
+    Verify(String),
+    Info,
+    Version,
+}
+
+impl NargoCommand {
+    // Function to get the command's name, which is also the name of its step in reports
+    pub fn name(&self) -> &'static str {
+        match self {
+            NargoCommand::New(_) => "new",
+            NargoCommand::Check => "check",
+            NargoCommand::Test(_) => "test",
+            NargoCommand::Execute => "execute",
+            NargoCommand::Prove(_) => "prove",
+            NargoCommand::Verify(_) => "verify",
+            NargoCommand::Info => "info",
+            NargoCommand::Version => "version",
+        }
+    }
+
+    // Function to get the arguments nargo is called with
+    pub fn args(&self) -> Vec<String> {
+        if *self == NargoCommand::Version {
+            return vec!["--version".to_string()];
+        }
+        let mut args = vec![self.name().to_string()];
+        match self {
+            NargoCommand::New(name) | NargoCommand::Prove(name) | NargoCommand::Verify(name) => {
+                args.push(name.clone());
+            }
+            NargoCommand::Test(Some(filter)) => args.push(filter.clone()),
+            _ => {}
+        }
+        args
+    }
+}
+
+pub type RunFuture<'a> = Pin<Box<dyn Future<Output = Result<Output, RunError>> + Send + 'a>>;
+
+// Something that runs nargo commands. The server uses the real nargo, while the integration tests
+// script a fake so they can run without a Noir install.
+pub trait Toolchain: Send + Sync {
+    // Function to run a command in a directory, returning its output whether or not it exited
+    // successfully. Each line of output is also passed to on_line as it is written. If the
+    // returned future is dropped the command is stopped.
+    fn run<'a>(
+        &'a self,
+        dir: &'a Path,
+        command: NargoCommand,
+        on_line: Option<LineSink>
+    ) -> RunFuture<'a>;
+}
+
+// Function to get the toolchain's version, as printed by `nargo --version`, running it in dir
+pub async fn version(toolchain: &dyn Toolchain, dir: &Path) -> Result<String, String> {
+    let output = match toolchain.run(dir, NargoCommand::Version, None).await {
+        Ok(output) => output,
+        Err(RunError::Io(e)) => {
+            return Err(format!("Failed to run nargo --version: {}", e));
+        }
+        Err(RunError::Limit(e)) => {
+            return Err(format!("Failed to run nargo --version: {}", e));
+        }
+    };
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("nargo --version failed: {}", stderr.trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// The nargo installed on the PATH, run under the sandbox's limits and isolation
+pub struct Nargo;
+
+impl Toolchain for Nargo {
+    fn run<'a>(
+        &'a self,
+        dir: &'a Path,
+        command: NargoCommand,
+        on_line: Option<LineSink>
+    ) -> RunFuture<'a> {
+        Box::pin(async move {
+            let mut cmd_obj = Command::new("nargo");
+            cmd_obj.args(command.args()).current_dir(dir);
+            sandbox::run_limited(cmd_obj, on_line).await
+        })
+    }
+}
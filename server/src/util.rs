@@ -0,0 +1,8 @@
+use std::sync::{ Mutex, MutexGuard };
+
+// Function to lock a mutex, carrying on with its data if a thread panicked while holding it. The
+// state behind each of the server's mutexes stays consistent between statements, so one failed
+// request does not take the others down with it.
+pub fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
@@ -0,0 +1,164 @@
+use crate::challenges::Challenge;
+use crate::jobs::Progress;
+use crate::lint;
+use crate::report::StepStatus;
+use crate::toolchain::Toolchain;
+use crate::vectors::run_vectors;
+use crate::workspace::WorkspaceManager;
+use crate::error::ApiError;
+use crate::inputs::table_toml;
+use crate::{
+    create_workspace,
+    grade_submission,
+    run_nargo_test,
+    write_helper_modules,
+    write_sources,
+};
+
+// Function to check every challenge's reference solution against its hidden test cases,
+// using the same pipeline as POST /execute. Returns false if any challenge failed.
+pub async fn validate_challenges(
+    toolchain: &dyn Toolchain,
+    workspaces: &WorkspaceManager,
+    challenges: &[Challenge]
+) -> bool {
+    let mut failed = 0;
+    for challenge in challenges {
+        for warning in lint_challenge(toolchain, workspaces, challenge).await {
+            eprintln!("warning: challenge {} ({}): {}", challenge.id, challenge.title, warning);
+        }
+        let problems = validate_challenge(toolchain, workspaces, challenge).await;
+        if problems.is_empty() {
+            println!("Challenge {} ({}): ok", challenge.id, challenge.title);
+        } else {
+            failed += 1;
+            eprintln!("Challenge {} ({}): FAILED", challenge.id, challenge.title);
+            for problem in problems {
+                eprintln!("  {}", problem);
+            }
+        }
+    }
+
+    println!("{} of {} challenges passed validation", challenges.len() - failed, challenges.len());
+    failed == 0
+}
+
+// Function to lint every challenge's hidden test cases, including compiling each of them
+// in isolation. Returns false if any warnings were found.
+pub async fn lint_challenges(
+    toolchain: &dyn Toolchain,
+    workspaces: &WorkspaceManager,
+    challenges: &[Challenge]
+) -> bool {
+    let mut clean = true;
+    for challenge in challenges {
+        let warnings = lint_challenge(toolchain, workspaces, challenge).await;
+        clean &= warnings.is_empty();
+        for warning in warnings {
+            eprintln!("warning: challenge {} ({}): {}", challenge.id, challenge.title, warning);
+        }
+    }
+    clean
+}
+
+async fn lint_challenge(
+    toolchain: &dyn Toolchain,
+    workspaces: &WorkspaceManager,
+    challenge: &Challenge
+) -> Vec<String> {
+    let mut warnings = lint::lint_test_cases(challenge);
+    warnings.extend(lint::check_test_cases(toolchain, workspaces, challenge).await);
+    warnings
+}
+
+// Function to validate a single challenge, returning every problem found
+async fn validate_challenge(
+    toolchain: &dyn Toolchain,
+    workspaces: &WorkspaceManager,
+    challenge: &Challenge
+) -> Vec<String> {
+    let mut problems = vec![];
+
+    // The hidden tests and test vectors must not all pass against the starter code,
+    // otherwise they check nothing
+    match run_tests_against(toolchain, workspaces, challenge, &challenge.starter_code).await {
+        Ok(true) => problems.push("hidden tests and vectors pass against starter code".to_string()),
+        Ok(false) => {}
+        Err(e) => {
+            problems.push(format!("could not run hidden tests against starter code: {}", e));
+        }
+    }
+
+    let solution = match &challenge.solution {
+        Some(solution) => solution,
+        None => {
+            problems.push("no reference solution (solution.nr)".to_string());
+            return problems;
+        }
+    };
+    let prover_inputs = match &solution.prover_inputs {
+        Some(prover_inputs) => prover_inputs,
+        None => {
+            problems.push("no prover inputs for the reference solution (solution.toml)".into());
+            return problems;
+        }
+    };
+
+    // The reference solution must pass test, check, prove and verify
+    let prover_toml = match table_toml(prover_inputs) {
+        Ok(prover_toml) => prover_toml,
+        Err(e) => {
+            problems.push(format!("prover inputs in solution.toml cannot be written: {}", e));
+            return problems;
+        }
+    };
+    let progress = Progress::default();
+    let graded = grade_submission(
+        toolchain,
+        workspaces,
+        None,
+        challenge,
+        &solution.code,
+        &prover_toml,
+        &progress
+    ).await;
+    match graded {
+        Ok(report) => {
+            let failed_step = report.steps.iter().find(|s| s.status == StepStatus::Failed);
+            if let Some(step) = failed_step {
+                let output = if step.stderr.is_empty() { &step.stdout } else { &step.stderr };
+                problems.push(format!("reference solution failed `{}`: {}", step.name, output));
+            }
+            for vector in report.vectors.iter().filter(|v| !v.passed) {
+                let message = format!("failed vector `{}`: {}", vector.name, vector.detail);
+                problems.push(format!("reference solution {}", message));
+            }
+        }
+        Err(e) => {
+            problems.push(format!("reference solution failed: {}", e));
+        }
+    }
+
+    problems
+}
+
+// Function to run nargo test on code combined with the challenge's hidden test cases, followed
+// by the challenge's test vectors. Returns whether everything passed, or an error if the project
+// could not be set up.
+async fn run_tests_against(
+    toolchain: &dyn Toolchain,
+    workspaces: &WorkspaceManager,
+    challenge: &Challenge,
+    code: &str
+) -> Result<bool, ApiError> {
+    let workspace = create_workspace(workspaces).await?;
+    let sources = write_sources(workspace.dir(), code, &challenge.test_cases).await?;
+    write_helper_modules(workspace.dir(), challenge).await?;
+    let project_sub_dir = workspace.project();
+    let progress = Progress::default();
+    if !run_nargo_test(toolchain, &project_sub_dir, None, &progress).await?.passed {
+        return Ok(false);
+    }
+    let vectors = run_vectors(toolchain, &project_sub_dir, &challenge.vectors, &sources).await?;
+    Ok(vectors.iter().all(|v| v.passed))
+}
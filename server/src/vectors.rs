@@ -0,0 +1,160 @@
+use crate::challenges::{ Expectation, TestVector };
+use crate::report::strip_ansi;
+use crate::sources::SourceMap;
+use crate::toolchain::{ NargoCommand, Toolchain };
+use crate::error::ApiError;
+use crate::inputs::table_toml;
+use crate::run_command;
+use serde_derive::Serialize;
+use std::path::Path;
+
+// What nargo prints when a program's constraints do not hold for its inputs, as opposed to when
+// it cannot compile the program or read the inputs
+const CONSTRAINT_FAILURES: &[&str] = &[
+    "failed constraint",
+    "cannot satisfy constraint",
+    "assertion failed",
+    "failed assertion",
+    "index out of bounds",
+    "divide by zero",
+];
+
+// BN254's scalar field order, which field elements are reduced by, as two 128-bit halves
+const FIELD_ORDER_HIGH: u128 = 0x30644e72e131a029b85045b68181585d;
+const FIELD_ORDER_LOW: u128 = 0x2833e84879b9709143e1f593f0000001;
+
+// Verdict for a single test vector
+#[derive(Serialize)]
+pub struct VectorResult {
+    pub name: String,
+    pub expected: Expectation,
+    pub passed: bool,
+    pub detail: String,
+}
+
+// Function to run every test vector through nargo prove in an already prepared project.
+// Prover.toml is overwritten with each vector's inputs in turn. nargo's output is redacted with
+// the project's source map, so the hidden tests stay hidden.
+pub async fn run_vectors(
+    toolchain: &dyn Toolchain,
+    dir_buf: &Path,
+    vectors: &[TestVector],
+    sources: &SourceMap
+) -> Result<Vec<VectorResult>, ApiError> {
+    let mut results = vec![];
+    for (i, vector) in vectors.iter().enumerate() {
+        let inputs = table_toml(&vector.inputs).map_err(|e| {
+            let message = format!("Inputs of vector `{}` cannot be written: {}", vector.name, e);
+            ApiError::Internal(message)
+        })?;
+        tokio::fs
+            ::write(dir_buf.join("Prover.toml"), inputs).await
+            .map_err(|e| ApiError::Internal(format!("Failed to write to Prover.toml: {}", e)))?;
+
+        let command = NargoCommand::Prove(format!("vector-{}", i + 1));
+        let output = run_command(toolchain, command, dir_buf).await?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let proved = output.status.success() && !stdout.contains("FAILED");
+
+        let (passed, detail) = match (vector.expect, proved) {
+            (Expectation::Success, true) => check_return_value(dir_buf, vector).await,
+            (Expectation::Success, false) => {
+                let stderr = sources.redact_output(&strip_ansi(&output.stderr));
+                (false, format!("Expected a proof to be generated, but proving failed: {}", stderr))
+            }
+            (Expectation::Failure, true) => {
+                (false, "Expected proving to fail, but a proof was generated".to_string())
+            }
+            // Failing for any other reason, such as inputs that do not match main's parameters,
+            // says nothing about the learner's constraints
+            (Expectation::Failure, false) => {
+                let stderr = sources.redact_output(&strip_ansi(&output.stderr));
+                if failed_constraint(&stdout) || failed_constraint(&stderr) {
+                    (true, "Proving failed as expected".to_string())
+                } else {
+                    let detail = format!(
+                        "Expected a constraint to fail, but proving failed for another reason: {}",
+                        stderr
+                    );
+                    (false, detail)
+                }
+            }
+        };
+
+        results.push(VectorResult {
+            name: vector.name.clone(),
+            expected: vector.expect,
+            passed,
+            detail,
+        });
+    }
+    Ok(results)
+}
+
+// Function to check whether nargo's output says a constraint or assertion did not hold
+fn failed_constraint(output: &str) -> bool {
+    let output = output.to_lowercase();
+    CONSTRAINT_FAILURES.iter().any(|failure| output.contains(failure))
+}
+
+// Function to compare main's return value, written to Verifier.toml by nargo prove,
+// with the value the vector expects
+async fn check_return_value(dir_buf: &Path, vector: &TestVector) -> (bool, String) {
+    let expected = match &vector.return_value {
+        Some(expected) => expected,
+        None => {
+            return (true, "Proof generated as expected".to_string());
+        }
+    };
+
+    let verifier_toml = tokio::fs::read_to_string(dir_buf.join("Verifier.toml")).await;
+    let actual = verifier_toml
+        .ok()
+        .and_then(|content| toml::from_str::<toml::value::Table>(&content).ok())
+        .and_then(|table| table.get("return").cloned());
+
+    match actual {
+        Some(actual) if normalize(&actual) == normalize(expected) => {
+            (true, "Proof generated and main returned the expected value".to_string())
+        }
+        Some(actual) => {
+            (false, format!("Expected main to return {}, but it returned {}", expected, actual))
+        }
+        None => (false, format!("Expected main to return {}, but it returned nothing", expected)),
+    }
+}
+
+// Function to put field values in a common form, so 10, "10" and "0x0a" compare equal, as do
+// -1 and the field element it stands for
+fn normalize(value: &toml::Value) -> toml::Value {
+    match value {
+        toml::Value::Integer(n) => toml::Value::String(field_hex(*n as i128)),
+        toml::Value::String(s) => {
+            let hex = match s.strip_prefix("0x") {
+                Some(hex) => Some(hex.trim_start_matches('0').to_lowercase()),
+                None => s.parse::<i128>().ok().map(field_hex),
+            };
+            match hex {
+                Some(hex) if hex.is_empty() => toml::Value::String("0".to_string()),
+                Some(hex) => toml::Value::String(hex),
+                None => value.clone(),
+            }
+        }
+        toml::Value::Array(values) => toml::Value::Array(values.iter().map(normalize).collect()),
+        _ => value.clone(),
+    }
+}
+
+// Function to write an integer as the field element it stands for, in hex, so a negative number
+// is reduced modulo the field order
+fn field_hex(n: i128) -> String {
+    if n >= 0 {
+        return format!("{:x}", n);
+    }
+    let magnitude = n.unsigned_abs();
+    let (high, low) = match FIELD_ORDER_LOW.checked_sub(magnitude) {
+        Some(low) => (FIELD_ORDER_HIGH, low),
+        None => (FIELD_ORDER_HIGH - 1, FIELD_ORDER_LOW.wrapping_sub(magnitude)),
+    };
+    format!("{:x}{:032x}", high, low)
+}
@@ -0,0 +1,245 @@
+use crate::sandbox::env_u64;
+use crate::template::ProjectTemplate;
+use crate::toolchain::Toolchain;
+use crate::util::lock;
+use std::collections::HashSet;
+use std::fmt;
+use std::io;
+use std::path::{ Path, PathBuf };
+use std::sync::atomic::{ AtomicU64, Ordering };
+use std::sync::{ Arc, Mutex };
+use std::time::Duration;
+use uuid::Uuid;
+
+// The directories nargo runs in, all under one root. Each run gets a Workspace, a copy of the
+// project template in a new directory, which is removed when the Workspace is dropped, whether
+// the run finished, failed, panicked or was cancelled. Directories left behind by an earlier
+// server are swept when the manager is created, and a janitor periodically removes any that
+// are no longer in use and stops new runs while the root uses too much disk. Configured with
+// environment variables:
+// - WORKSPACE_DIR: the root (default tmp/noir_projects, under the current directory)
+// - WORKSPACE_MAX_MB: disk the root can use before new runs are turned away, 0 for no limit
+//   (default 10240)
+// - WORKSPACE_JANITOR_SECONDS: how often the janitor runs, 0 to never run it (default 60)
+pub struct WorkspaceManager {
+    root: PathBuf,
+    template: ProjectTemplate,
+    max_bytes: u64,
+    // Bytes used under the root when the janitor last looked
+    usage: AtomicU64,
+    // Shared with the tasks removing workspaces, which only let go of a directory once it is gone
+    active: Arc<Mutex<HashSet<PathBuf>>>,
+}
+
+// Error returned when a workspace cannot be created
+#[derive(Debug)]
+pub enum WorkspaceError {
+    // The root uses more disk than WORKSPACE_MAX_MB allows
+    DiskFull,
+    Io(io::Error),
+}
+
+impl fmt::Display for WorkspaceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WorkspaceError::DiskFull => write!(f, "Too little disk space left to run nargo"),
+            WorkspaceError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for WorkspaceError {}
+
+impl From<io::Error> for WorkspaceError {
+    fn from(e: io::Error) -> Self {
+        WorkspaceError::Io(e)
+    }
+}
+
+// A project directory for one run, removed when it is dropped
+pub struct Workspace<'a> {
+    dir: PathBuf,
+    manager: &'a WorkspaceManager,
+}
+
+impl WorkspaceManager {
+    // Function to sweep what an earlier server left in root, then create the project template
+    pub async fn new(
+        toolchain: &dyn Toolchain,
+        root: &Path,
+        max_bytes: u64
+    ) -> Result<Self, String> {
+        tokio::fs
+            ::create_dir_all(root).await
+            .map_err(|e| format!("Failed to create dir {}: {}", root.display(), e))?;
+        let swept = sweep(root, |_| false).await.map_err(|e| {
+            format!("Failed to sweep {}: {}", root.display(), e)
+        })?;
+        if swept > 0 {
+            println!("Removed {} stale project directories from {}", swept, root.display());
+        }
+        let template = ProjectTemplate::create(toolchain, root).await?;
+        Ok(WorkspaceManager {
+            root: root.to_path_buf(),
+            template,
+            max_bytes,
+            usage: AtomicU64::new(0),
+            active: Arc::new(Mutex::new(HashSet::new())),
+        })
+    }
+
+    // Function to create the manager configured by the environment
+    pub async fn from_env(toolchain: &dyn Toolchain) -> Result<Self, String> {
+        let root = match std::env::var("WORKSPACE_DIR") {
+            Ok(dir) => PathBuf::from(dir),
+            Err(_) => {
+                let cwd = std::env::current_dir().map_err(|e| e.to_string())?;
+                cwd.join("tmp/noir_projects")
+            }
+        };
+        let max_bytes = env_u64("WORKSPACE_MAX_MB", 10240) * 1024 * 1024;
+        WorkspaceManager::new(toolchain, &root, max_bytes).await
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    // Function to create a workspace holding a copy of the project template
+    pub async fn create(&self) -> Result<Workspace<'_>, WorkspaceError> {
+        if self.max_bytes > 0 && self.usage.load(Ordering::Relaxed) > self.max_bytes {
+            return Err(WorkspaceError::DiskFull);
+        }
+        let dir = self.root.join(Uuid::new_v4().to_string());
+        // Registered before the directory exists, so the janitor never takes it for a leftover
+        lock(&self.active).insert(dir.clone());
+        let workspace = Workspace { dir, manager: self };
+        tokio::fs::create_dir(&workspace.dir).await?;
+        self.template.copy_to(&workspace.dir).await?;
+        Ok(workspace)
+    }
+
+    // Function to remove directories no run is using, and measure how much disk the root uses.
+    // Whether a directory is in use is checked as it is reached rather than up front, as a run
+    // may create its workspace while the sweep is going on.
+    pub async fn clean(&self) -> io::Result<()> {
+        let template = self.template.dir();
+        let in_use = |dir: &Path| dir == template || lock(&self.active).contains(dir);
+        let swept = sweep(&self.root, in_use).await?;
+        if swept > 0 {
+            println!("Removed {} leftover project directories", swept);
+        }
+
+        let usage = disk_usage(&self.root).await?;
+        self.usage.store(usage, Ordering::Relaxed);
+        if self.max_bytes > 0 && usage > self.max_bytes {
+            eprintln!(
+                "Project directories use {} MB, over the limit of {} MB",
+                usage / 1024 / 1024,
+                self.max_bytes / 1024 / 1024
+            );
+        }
+        Ok(())
+    }
+
+    // Function to clean up every interval, forever
+    pub async fn run_janitor(&self, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = self.clean().await {
+                eprintln!("Failed to clean {}: {}", self.root.display(), e);
+            }
+        }
+    }
+}
+
+impl Workspace<'_> {
+    // Function to get the workspace's directory
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    // Function to get the Noir project inside the workspace, where nargo runs
+    pub fn project(&self) -> PathBuf {
+        self.dir.join("project")
+    }
+}
+
+impl Drop for Workspace<'_> {
+    // Removing a large target directory takes a while, so it is done on a blocking thread
+    // instead of the async runtime's. The directory stays in use until it is gone, so the
+    // janitor does not try to remove it at the same time.
+    fn drop(&mut self) {
+        let dir = std::mem::take(&mut self.dir);
+        let active = Arc::clone(&self.manager.active);
+        let remove = move || {
+            if let Err(e) = std::fs::remove_dir_all(&dir) {
+                if e.kind() != io::ErrorKind::NotFound {
+                    // Left for the janitor to sweep
+                    eprintln!("Failed to remove directory {:?}. Error: {}", dir, e);
+                }
+            }
+            lock(&active).remove(&dir);
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn_blocking(remove);
+            }
+            Err(_) => remove(),
+        }
+    }
+}
+
+// Function to remove the workspaces and templates under root that are not in use, returning
+// how many were removed. Anything else under root is left alone.
+async fn sweep(root: &Path, in_use: impl Fn(&Path) -> bool) -> io::Result<usize> {
+    let mut swept = 0;
+    let mut entries = tokio::fs::read_dir(root).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let name = name.strip_prefix("template-").unwrap_or(&name);
+        if Uuid::parse_str(name).is_err() || in_use(&entry.path()) {
+            continue;
+        }
+        match tokio::fs::remove_dir_all(entry.path()).await {
+            Ok(()) => {
+                swept += 1;
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(e);
+            }
+        }
+    }
+    Ok(swept)
+}
+
+// Function to add up the size of every file under a directory
+async fn disk_usage(dir: &Path) -> io::Result<u64> {
+    let mut total = 0;
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let mut entries = match tokio::fs::read_dir(&dir).await {
+            Ok(entries) => entries,
+            // Removed while it was being measured
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                continue;
+            }
+            Err(e) => {
+                return Err(e);
+            }
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let Ok(metadata) = entry.metadata().await else {
+                continue;
+            };
+            if metadata.is_dir() {
+                pending.push(entry.path());
+            } else {
+                total += metadata.len();
+            }
+        }
+    }
+    Ok(total)
+}
@@ -0,0 +1,170 @@
+mod common;
+
+use common::{ commands, execution, json, post, routes, temp_dir, wait_for_command };
+use common::fake::{ FakeNargo, FakeRun };
+use server::artifacts::ArtifactCache;
+use server::cache::{ CacheStatus, Lookup, ResultCache };
+use server::report::{ StepReport, StepStatus };
+use server::sandbox::LimitKind;
+use std::sync::Arc;
+use std::time::Duration;
+
+const CODE: &str = "fn main(x : Field, y : pub Field) {\n    assert(x != y);\n}\n";
+
+fn runs(fake: &FakeNargo, name: &str) -> usize {
+    commands(fake)
+        .into_iter()
+        .filter(|command| *command == name)
+        .count()
+}
+
+#[tokio::test]
+async fn resubmitting_identical_code_is_served_from_the_cache() {
+    let fake = Arc::new(FakeNargo::new());
+    let routes = routes(&fake).await;
+    let body = execution(1, CODE, &[("x", "1"), ("y", "2")]);
+
+    let first = post(&routes, "/execute", &body).await;
+    assert_eq!(first.status(), 200);
+    assert_eq!(first.headers()["X-Cache"], "miss");
+    let ran = commands(&fake);
+
+    let second = post(&routes, "/execute", &body).await;
+    assert_eq!(second.status(), 200);
+    assert_eq!(second.headers()["X-Cache"], "hit");
+    assert_eq!(second.headers()["X-Cache-Key"], first.headers()["X-Cache-Key"]);
+    assert!(second.headers().contains_key("X-Cache-Age-Ms"));
+    assert_eq!(json(&second), json(&first));
+    assert_eq!(commands(&fake), ran);
+}
+
+#[tokio::test]
+async fn different_inputs_or_steps_are_not_shared() {
+    let fake = Arc::new(FakeNargo::new());
+    let routes = routes(&fake).await;
+
+    let body = execution(1, CODE, &[("x", "1"), ("y", "2")]);
+    let first = post(&routes, "/execute", &body).await;
+    let inputs = post(&routes, "/execute", &execution(1, CODE, &[("x", "1"), ("y", "3")])).await;
+    let check = post(&routes, "/execute_check", &body).await;
+
+    assert_eq!(first.headers()["X-Cache"], "miss");
+    assert_eq!(inputs.headers()["X-Cache"], "miss");
+    assert_eq!(check.headers()["X-Cache"], "miss");
+    assert_ne!(inputs.headers()["X-Cache-Key"], first.headers()["X-Cache-Key"]);
+    assert_eq!(runs(&fake, "verify"), 2);
+}
+
+#[tokio::test]
+async fn identical_requests_running_at_once_share_one_run() {
+    let fake = Arc::new(FakeNargo::new());
+    fake.on("test", FakeRun::success().delay(Duration::from_millis(300)));
+    let routes = routes(&fake).await;
+    let body = execution(1, CODE, &[("x", "1"), ("y", "2")]);
+
+    let first = tokio::spawn({
+        let routes = routes.clone();
+        let body = body.clone();
+        async move { post(&routes, "/execute", &body).await }
+    });
+    wait_for_command(&fake, "test").await;
+
+    let second = post(&routes, "/execute", &body).await;
+    assert_eq!(second.status(), 200);
+    assert_eq!(second.headers()["X-Cache"], "shared");
+    let first = first.await.unwrap();
+    assert_eq!(first.headers()["X-Cache"], "miss");
+    assert_eq!(json(&second), json(&first));
+    assert_eq!(runs(&fake, "test"), 1);
+}
+
+#[tokio::test]
+async fn errors_are_not_cached() {
+    let fake = Arc::new(FakeNargo::new());
+    fake.on("test", FakeRun::limit(LimitKind::WallClock));
+    let routes = routes(&fake).await;
+    let body = execution(1, CODE, &[("x", "1"), ("y", "2")]);
+
+    assert_eq!(post(&routes, "/execute", &body).await.status(), 408);
+    assert_eq!(post(&routes, "/execute", &body).await.status(), 408);
+    assert_eq!(runs(&fake, "test"), 2);
+}
+
+#[tokio::test]
+async fn results_expire_and_the_least_recently_used_are_evicted() {
+    let value = serde_json::json!({ "passed": true });
+    let store = |cache: &Arc<ResultCache>, key: &str| {
+        let cache = Arc::clone(cache);
+        let key = key.to_string();
+        let value = value.clone();
+        async move {
+            match cache.lookup(&key).await {
+                Lookup::Run(pending) => pending.store(&value),
+                Lookup::Cached(_) => panic!("{} was already cached", key),
+            }
+        }
+    };
+    let status = |cache: &Arc<ResultCache>, key: &str| {
+        let cache = Arc::clone(cache);
+        let key = key.to_string();
+        async move {
+            match cache.lookup(&key).await {
+                Lookup::Cached(cached) => Some(cached.status),
+                Lookup::Run(_) => None,
+            }
+        }
+    };
+
+    let cache = Arc::new(ResultCache::new("v1", 2, Duration::from_secs(60)));
+    store(&cache, "a").await;
+    store(&cache, "b").await;
+    assert_eq!(status(&cache, "a").await, Some(CacheStatus::Hit));
+    store(&cache, "c").await;
+    assert_eq!(status(&cache, "b").await, None);
+    assert_eq!(status(&cache, "a").await, Some(CacheStatus::Hit));
+
+    let cache = Arc::new(ResultCache::new("v1", 2, Duration::ZERO));
+    store(&cache, "a").await;
+    assert_eq!(status(&cache, "a").await, None);
+
+    let disabled = Arc::new(ResultCache::new("v1", 0, Duration::from_secs(60)));
+    store(&disabled, "a").await;
+    assert_eq!(status(&disabled, "a").await, None);
+}
+
+#[tokio::test]
+async fn artifacts_are_restored_for_the_same_source() {
+    let project = temp_dir("project");
+    std::fs::create_dir_all(project.join("src")).unwrap();
+    std::fs::create_dir_all(project.join("target")).unwrap();
+    std::fs::write(project.join("Nargo.toml"), "[package]\nname = \"project\"\n").unwrap();
+    std::fs::write(project.join("src/main.nr"), "fn main() {}\n").unwrap();
+    std::fs::write(project.join("target/project.json"), "{}").unwrap();
+    let steps = vec![StepReport::skipped("check")];
+
+    let artifacts = temp_dir("artifacts");
+    let cache = ArtifactCache::new(&artifacts, "v1", 1);
+    let key = cache.key(&project).await.unwrap();
+    assert!(cache.restore(&key, &project).await.is_none());
+    cache.save(&key, &project, &steps).await.unwrap();
+
+    std::fs::remove_dir_all(project.join("target")).unwrap();
+    let restored = cache.restore(&key, &project).await.unwrap();
+    assert_eq!(restored.len(), 1);
+    assert_eq!(restored[0].status, StepStatus::Skipped);
+    assert!(restored[0].cached);
+    assert_eq!(std::fs::read_to_string(project.join("target/project.json")).unwrap(), "{}");
+
+    // Other source or another nargo version gets another key
+    let other_artifacts = temp_dir("artifacts");
+    let other_version = ArtifactCache::new(&other_artifacts, "v2", 1);
+    assert_ne!(other_version.key(&project).await.unwrap(), key);
+    std::fs::write(project.join("src/main.nr"), "fn main() { }\n").unwrap();
+    let edited = cache.key(&project).await.unwrap();
+    assert_ne!(edited, key);
+
+    // With room for one artifact, saving another evicts the first
+    cache.save(&edited, &project, &steps).await.unwrap();
+    assert!(cache.restore(&key, &project).await.is_none());
+    assert!(cache.restore(&edited, &project).await.is_some());
+}
@@ -0,0 +1,313 @@
+mod common;
+
+use common::{
+    add_challenge,
+    get,
+    json,
+    metadata,
+    routes,
+    routes_for,
+    temp_challenges,
+    Routes,
+    ADMIN_TOKEN,
+};
+use common::fake::FakeNargo;
+use server::challenges::{ load_challenges, ChallengeStore };
+use server::queue::JobQueue;
+use std::path::Path;
+use std::sync::Arc;
+
+#[tokio::test]
+async fn lists_challenges_in_id_order() {
+    let routes = routes(&Arc::new(FakeNargo::new())).await;
+
+    let response = get(&routes, "/challenges").await;
+    assert_eq!(response.status(), 200);
+    let challenges = json(&response);
+    let ids: Vec<u64> = challenges
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| c["id"].as_u64().unwrap())
+        .collect();
+    assert_eq!(ids, vec![1, 2, 3]);
+    assert!(challenges[0].get("description").is_none());
+}
+
+#[tokio::test]
+async fn filters_challenges_by_tag_and_difficulty() {
+    let routes = routes(&Arc::new(FakeNargo::new())).await;
+
+    let tagged = json(&get(&routes, "/challenges?tag=ASSERT").await);
+    assert_eq!(tagged.as_array().unwrap().len(), 1);
+    assert_eq!(tagged[0]["id"], 1);
+
+    let advanced = json(&get(&routes, "/challenges?difficulty=advanced").await);
+    assert!(advanced.as_array().unwrap().iter().all(|c| c["difficulty"] == "advanced"));
+}
+
+#[tokio::test]
+async fn gets_a_challenge_without_its_test_cases() {
+    let routes = routes(&Arc::new(FakeNargo::new())).await;
+
+    let response = get(&routes, "/challenges/1").await;
+    assert_eq!(response.status(), 200);
+    let challenge = json(&response);
+    assert_eq!(challenge["title"], "Assert");
+    assert!(!challenge["description"].as_str().unwrap().is_empty());
+    assert!(challenge["starter_code"].as_str().unwrap().contains("fn main"));
+    assert_eq!(challenge["test_cases"], serde_json::json!([]));
+}
+
+#[tokio::test]
+async fn unknown_challenges_and_routes_are_not_found() {
+    let routes = routes(&Arc::new(FakeNargo::new())).await;
+
+    let response = get(&routes, "/challenges/99").await;
+    assert_eq!(response.status(), 404);
+    assert_eq!(json(&response)["code"], "challenge_not_found");
+    let response = get(&routes, "/nothing-here").await;
+    assert_eq!(response.status(), 404);
+    assert_eq!(json(&response), serde_json::json!({ "code": "not_found", "message": "Not found" }));
+}
+
+#[tokio::test]
+async fn a_malformed_query_is_a_bad_request() {
+    let routes = routes(&Arc::new(FakeNargo::new())).await;
+
+    let response = get(&routes, "/challenges?difficulty=impossible").await;
+    assert_eq!(response.status(), 400);
+    assert_eq!(json(&response)["code"], "bad_request");
+}
+
+#[tokio::test]
+async fn lists_tracks_with_prerequisites_first() {
+    let routes = routes(&Arc::new(FakeNargo::new())).await;
+
+    let response = get(&routes, "/tracks").await;
+    assert_eq!(response.status(), 200);
+    let tracks = json(&response);
+    let tracks = tracks.as_array().unwrap();
+    assert!(!tracks.is_empty());
+    let mut seen = vec![];
+    for challenge in tracks.iter().flat_map(|track| track["challenges"].as_array().unwrap()) {
+        for prerequisite in challenge["prerequisites"].as_array().unwrap() {
+            assert!(seen.contains(prerequisite), "{} comes before its prerequisites", challenge);
+        }
+        seen.push(challenge["id"].clone());
+    }
+}
+
+#[tokio::test]
+async fn reload_needs_the_admin_token() {
+    let routes = routes(&Arc::new(FakeNargo::new())).await;
+
+    let response = warp::test
+        ::request()
+        .method("POST")
+        .path("/admin/reload")
+        .reply(&routes.filter).await;
+    assert_eq!(response.status(), 404);
+
+    let response = warp::test
+        ::request()
+        .method("POST")
+        .path("/admin/reload")
+        .header("x-admin-token", "wrong")
+        .reply(&routes.filter).await;
+    assert_eq!(response.status(), 404);
+
+    let response = warp::test
+        ::request()
+        .method("POST")
+        .path("/admin/reload")
+        .header("x-admin-token", ADMIN_TOKEN)
+        .reply(&routes.filter).await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(json(&response)["challenges"], 3);
+}
+
+// Function to load a challenges directory that is expected to be invalid, returning each error
+fn load_errors(dir: &Path) -> Vec<String> {
+    match load_challenges(dir) {
+        Ok(_) => panic!("{:?} loaded, but is invalid", dir),
+        Err(errors) => errors.iter().map(|e| e.to_string()).collect(),
+    }
+}
+
+#[test]
+fn helpers_cannot_replace_the_hidden_tests_module() {
+    let dir = temp_challenges(7, &[("helpers/hidden_tests.nr", "fn helper() {}\n")]);
+
+    let errors = load_errors(&dir);
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    let message = "helpers/hidden_tests.nr: File name must be a valid Noir module name";
+    assert!(errors[0].ends_with(message), "{:?}", errors);
+}
+
+#[test]
+fn prerequisites_must_not_form_a_cycle() {
+    let first = metadata(1) + "prerequisites = [2]\n";
+    let dir = temp_challenges(1, &[("challenge.toml", &first)]);
+    let second = metadata(2) + "prerequisites = [1]\n";
+    let package = add_challenge(&dir, 2, &[("challenge.toml", &second)]);
+
+    // Both challenges on the cycle are reported
+    let errors = load_errors(&dir);
+    let expected: Vec<String> = [dir.join("01-test"), package]
+        .iter()
+        .map(|package| {
+            format!(
+                "{}: `prerequisites`: prerequisites form a cycle among challenges 1, 2",
+                package.join("challenge.toml").display()
+            )
+        })
+        .collect();
+    assert_eq!(errors, expected);
+}
+
+#[test]
+fn prerequisites_must_be_known_challenges() {
+    let contents = metadata(7) + "prerequisites = [3]\n";
+    let dir = temp_challenges(7, &[("challenge.toml", &contents)]);
+
+    let errors = load_errors(&dir);
+    let error = format!(
+        "{}: `prerequisites`: unknown prerequisite challenge 3",
+        dir.join("07-test/challenge.toml").display()
+    );
+    assert_eq!(errors, vec![error]);
+}
+
+#[test]
+fn prerequisites_must_not_be_listed_twice() {
+    let dir = temp_challenges(1, &[]);
+    let contents = metadata(2) + "prerequisites = [1, 1]\n";
+    let package = add_challenge(&dir, 2, &[("challenge.toml", &contents)]);
+
+    let errors = load_errors(&dir);
+    let error = format!(
+        "{}: `prerequisites`: prerequisite 1 is listed twice",
+        package.join("challenge.toml").display()
+    );
+    assert_eq!(errors, vec![error]);
+}
+
+#[test]
+fn metadata_with_invalid_syntax_is_reported_with_its_position() {
+    let dir = temp_challenges(7, &[("challenge.toml", "id = 7\ntitle = \"Test\n")]);
+
+    let errors = load_errors(&dir);
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    let path = dir.join("07-test/challenge.toml");
+    assert!(errors[0].starts_with(&format!("{}: ", path.display())), "{:?}", errors);
+    assert!(errors[0].contains("line 2"), "{:?}", errors);
+}
+
+#[test]
+fn metadata_fields_must_not_be_empty() {
+    let contents = metadata(7).replace("title = \"Test\"", "title = \" \"");
+    let dir = temp_challenges(7, &[("challenge.toml", &contents)]);
+
+    let errors = load_errors(&dir);
+    let path = dir.join("07-test/challenge.toml");
+    assert_eq!(errors, vec![format!("{}: `title`: must not be empty", path.display())]);
+}
+
+#[test]
+fn challenge_ids_must_be_unique() {
+    let dir = temp_challenges(7, &[]);
+    let package = add_challenge(&dir, 8, &[("challenge.toml", &metadata(7))]);
+
+    let errors = load_errors(&dir);
+    let error = format!(
+        "{}: `id`: id 7 is already used by {}",
+        package.join("challenge.toml").display(),
+        dir.join("07-test").display()
+    );
+    assert_eq!(errors, vec![error]);
+}
+
+#[test]
+fn packages_need_a_tests_directory() {
+    let dir = temp_challenges(7, &[]);
+    std::fs::remove_dir_all(dir.join("07-test/tests")).unwrap();
+
+    let errors = load_errors(&dir);
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    let path = dir.join("07-test/tests");
+    let prefix = format!("{}: Failed to read directory", path.display());
+    assert!(errors[0].starts_with(&prefix), "{:?}", errors);
+}
+
+#[test]
+fn descriptions_must_not_be_empty() {
+    let dir = temp_challenges(7, &[("description.md", "\n")]);
+
+    let errors = load_errors(&dir);
+    let path = dir.join("07-test/description.md");
+    assert_eq!(errors, vec![format!("{}: Description must not be empty", path.display())]);
+}
+
+// Function to ask the server to reload its challenges
+async fn reload(routes: &Routes) -> warp::http::Response<warp::hyper::body::Bytes> {
+    warp::test
+        ::request()
+        .method("POST")
+        .path("/admin/reload")
+        .header("x-admin-token", ADMIN_TOKEN)
+        .reply(&routes.filter).await
+}
+
+#[tokio::test]
+async fn reloading_serves_edited_packages() {
+    let dir = temp_challenges(7, &[]);
+    let routes = routes_for(&Arc::new(FakeNargo::new()), &dir, JobQueue::new(1, 8, 8)).await;
+    assert_eq!(json(&get(&routes, "/challenges/7").await)["title"], "Test");
+
+    let contents = metadata(7).replace("title = \"Test\"", "title = \"Edited\"");
+    std::fs::write(dir.join("07-test/challenge.toml"), contents).unwrap();
+    let response = reload(&routes).await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(json(&response)["challenges"], 1);
+    assert_eq!(json(&get(&routes, "/challenges/7").await)["title"], "Edited");
+}
+
+#[tokio::test]
+async fn a_broken_package_keeps_the_previous_challenges() {
+    let dir = temp_challenges(7, &[]);
+    let routes = routes_for(&Arc::new(FakeNargo::new()), &dir, JobQueue::new(1, 8, 8)).await;
+
+    let description = dir.join("07-test/description.md");
+    std::fs::write(&description, "").unwrap();
+    let response = reload(&routes).await;
+    assert_eq!(response.status(), 500);
+    let message = format!("{}: Description must not be empty", description.display());
+    assert_eq!(json(&response)["message"], message);
+
+    let response = get(&routes, "/challenges/7").await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(json(&response)["description"], "A challenge for tests");
+}
+
+#[test]
+fn packages_are_only_reloaded_when_they_change() {
+    let dir = temp_challenges(7, &[]);
+    let store = ChallengeStore::load(&dir).unwrap();
+    assert!(store.reload_if_changed().is_none());
+
+    add_challenge(&dir, 8, &[]);
+    assert_eq!(store.reload_if_changed().unwrap().unwrap(), 2);
+    assert_eq!(store.snapshot().len(), 2);
+    assert!(store.reload_if_changed().is_none());
+
+    // A broken package is reported, while the challenges loaded before stay in use
+    std::fs::write(dir.join("08-test/challenge.toml"), "id = ").unwrap();
+    assert!(store.reload_if_changed().unwrap().is_err());
+    let ids: Vec<u32> = store
+        .snapshot()
+        .iter()
+        .map(|challenge| challenge.id)
+        .collect();
+    assert_eq!(ids, vec![7, 8]);
+}
@@ -0,0 +1,227 @@
+// FakeNargo, a scripted stand-in for nargo, so the integration tests run without a Noir install
+
+use server::sandbox::{ LimitExceeded, LimitKind, LineSink, RunError, Stream };
+use server::toolchain::{ NargoCommand, RunFuture, Toolchain };
+use server::util::lock;
+use std::os::unix::process::ExitStatusExt;
+use std::path::{ Path, PathBuf };
+use std::process::{ ExitStatus, Output };
+use std::sync::Mutex;
+use std::time::Duration;
+
+// What a scripted command does when FakeNargo runs it
+#[derive(Clone, Default)]
+pub struct FakeRun {
+    exit_code: i32,
+    stdout: String,
+    stderr: String,
+    delay: Duration,
+    limit: Option<LimitKind>,
+    // Files written relative to the project directory, like Verifier.toml after proving
+    files: Vec<(String, String)>,
+}
+
+impl FakeRun {
+    pub fn success() -> Self {
+        FakeRun::default()
+    }
+
+    pub fn failure(exit_code: i32) -> Self {
+        FakeRun { exit_code, ..FakeRun::default() }
+    }
+
+    // Function to stop the command as if it hit a resource limit
+    pub fn limit(kind: LimitKind) -> Self {
+        FakeRun { limit: Some(kind), ..FakeRun::default() }
+    }
+
+    pub fn stdout(mut self, stdout: &str) -> Self {
+        self.stdout = stdout.to_string();
+        self
+    }
+
+    pub fn stderr(mut self, stderr: &str) -> Self {
+        self.stderr = stderr.to_string();
+        self
+    }
+
+    // Function to make the command take a while, after writing its output
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    pub fn write_file(mut self, path: &str, contents: &str) -> Self {
+        self.files.push((path.to_string(), contents.to_string()));
+        self
+    }
+}
+
+// A command FakeNargo was asked to run
+#[derive(Debug, Clone)]
+pub struct FakeCall {
+    pub dir: PathBuf,
+    pub command: NargoCommand,
+}
+
+// A scripted stand-in for nargo, for tests. `new` lays out a project like nargo does, `check`
+// writes a missing Prover.toml with an empty value for each of main's parameters, `prove` and
+// `verify` compile the program into `target` unless it was already compiled from the same
+// source, and every command succeeds without output unless scripted otherwise.
+#[derive(Default)]
+pub struct FakeNargo {
+    // Command name, text the project must contain for the script to apply, and what to do.
+    // Later scripts take precedence.
+    scripts: Mutex<Vec<(&'static str, Option<String>, FakeRun)>>,
+    calls: Mutex<Vec<FakeCall>>,
+    compiles: Mutex<usize>,
+}
+
+impl FakeNargo {
+    pub fn new() -> Self {
+        FakeNargo::default()
+    }
+
+    // Function to script what a command (as named by NargoCommand::name) does
+    pub fn on(&self, command: &'static str, run: FakeRun) -> &Self {
+        lock(&self.scripts).push((command, None, run));
+        self
+    }
+
+    // Function to script what a command does when one of the project's source files or its
+    // Prover.toml contains the given text
+    pub fn on_matching(&self, command: &'static str, contains: &str, run: FakeRun) -> &Self {
+        lock(&self.scripts).push((command, Some(contains.to_string()), run));
+        self
+    }
+
+    // Function to get every command run so far, in order
+    pub fn calls(&self) -> Vec<FakeCall> {
+        lock(&self.calls).clone()
+    }
+
+    // Function to get how many times a program was compiled into `target`, rather than reused
+    pub fn compiles(&self) -> usize {
+        *lock(&self.compiles)
+    }
+
+    fn script(&self, command: &NargoCommand, project: &str) -> Option<FakeRun> {
+        lock(&self.scripts)
+            .iter()
+            .rev()
+            .filter(|(name, _, _)| *name == command.name())
+            .find(|(_, contains, _)| contains.as_ref().is_none_or(|text| project.contains(text)))
+            .map(|(_, _, run)| run.clone())
+    }
+}
+
+impl Toolchain for FakeNargo {
+    fn run<'a>(
+        &'a self,
+        dir: &'a Path,
+        command: NargoCommand,
+        on_line: Option<LineSink>
+    ) -> RunFuture<'a> {
+        Box::pin(async move {
+            lock(&self.calls).push(FakeCall { dir: dir.to_path_buf(), command: command.clone() });
+            let code = tokio::fs
+                ::read_to_string(dir.join("src/main.nr")).await
+                .unwrap_or_default();
+            let prover_toml = tokio::fs
+                ::read_to_string(dir.join("Prover.toml")).await
+                .unwrap_or_default();
+
+            match &command {
+                NargoCommand::New(name) => {
+                    let project = dir.join(name);
+                    tokio::fs::create_dir_all(project.join("src")).await?;
+                    let manifest = format!("[package]\nname = \"{}\"\ntype = \"bin\"\n", name);
+                    tokio::fs::write(project.join("Nargo.toml"), manifest).await?;
+                    tokio::fs::write(project.join("src/main.nr"), "fn main() {}\n").await?;
+                }
+                NargoCommand::Check if prover_toml.is_empty() => {
+                    tokio::fs::write(dir.join("Prover.toml"), prover_toml_for(&code)).await?;
+                }
+                _ => {}
+            }
+
+            let mut sources = code.clone();
+            for source in other_sources(dir).await {
+                sources.push('\n');
+                sources.push_str(&source);
+            }
+            if matches!(command, NargoCommand::Prove(_) | NargoCommand::Verify(_)) {
+                // The fake's compiled program is the source it was compiled from
+                let program = dir.join("target/project.json");
+                let compiled = tokio::fs::read_to_string(&program).await.unwrap_or_default();
+                if compiled != sources {
+                    *lock(&self.compiles) += 1;
+                    tokio::fs::create_dir_all(dir.join("target")).await?;
+                    tokio::fs::write(&program, &sources).await?;
+                }
+            }
+            let project = format!("{}\n{}", sources, prover_toml);
+            let run = self.script(&command, &project).unwrap_or_default();
+            for (path, contents) in &run.files {
+                let path = dir.join(path);
+                if let Some(parent) = path.parent() {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+                tokio::fs::write(path, contents).await?;
+            }
+            if let Some(on_line) = &on_line {
+                for line in run.stdout.lines() {
+                    on_line(Stream::Stdout, line);
+                }
+                for line in run.stderr.lines() {
+                    on_line(Stream::Stderr, line);
+                }
+            }
+            tokio::time::sleep(run.delay).await;
+            if let Some(kind) = run.limit {
+                return Err(RunError::Limit(LimitExceeded { kind }));
+            }
+
+            Ok(Output {
+                status: ExitStatus::from_raw(run.exit_code << 8),
+                stdout: run.stdout.into_bytes(),
+                stderr: run.stderr.into_bytes(),
+            })
+        })
+    }
+}
+
+// Function to read the source files of a project other than src/main.nr, in name order
+async fn other_sources(dir: &Path) -> Vec<String> {
+    let mut paths = vec![];
+    if let Ok(mut entries) = tokio::fs::read_dir(dir.join("src")).await {
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            if entry.file_name() != "main.nr" {
+                paths.push(entry.path());
+            }
+        }
+    }
+    paths.sort();
+    let mut sources = vec![];
+    for path in paths {
+        sources.push(tokio::fs::read_to_string(path).await.unwrap_or_default());
+    }
+    sources
+}
+
+// Function to write the Prover.toml nargo check would, with an empty value for each of main's
+// parameters
+fn prover_toml_for(code: &str) -> String {
+    let Some(start) = code.find("fn main(") else {
+        return String::new();
+    };
+    let params = &code[start + "fn main(".len()..];
+    let params = &params[..params.find(')').unwrap_or(params.len())];
+    params
+        .split(',')
+        .filter_map(|param| param.split(':').next())
+        .map(|name| name.trim().trim_start_matches("mut ").trim())
+        .filter(|name| !name.is_empty())
+        .map(|name| format!("{} = \"\"\n", name))
+        .collect()
+}
@@ -0,0 +1,209 @@
+// Shared setup for the integration tests, which run every route against a scripted FakeNargo
+#![allow(dead_code)]
+
+pub mod fake;
+
+use fake::FakeNargo;
+use serde_json::Value;
+use server::artifacts::ArtifactCache;
+use server::cache::ResultCache;
+use server::challenges::ChallengeStore;
+use server::queue::JobQueue;
+use server::toolchain::NargoCommand;
+use server::workspace::WorkspaceManager;
+use std::ops::Deref;
+use std::path::{ Path, PathBuf };
+use std::sync::Arc;
+use std::time::Duration;
+use warp::filters::BoxedFilter;
+use warp::http::Response;
+use warp::hyper::body::Bytes;
+use warp::reply::Reply;
+use warp::Filter;
+
+pub const ADMIN_TOKEN: &str = "secret";
+
+// The server's routes, along with the temporary directories they keep their files in, which
+// are removed once the last clone is dropped
+#[derive(Clone)]
+pub struct Routes {
+    pub filter: BoxedFilter<(warp::reply::Response,)>,
+    _dirs: Arc<Vec<TempDir>>,
+}
+
+// A directory under the system's temporary directory, removed with everything in it when it is
+// dropped. It is not created, so it can be given to code that creates it.
+pub struct TempDir(PathBuf);
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+pub fn challenges_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("challenges")
+}
+
+// Function to build the server's routes on the repository's challenges, with room for two jobs
+// to run and eight to wait
+pub async fn routes(fake: &Arc<FakeNargo>) -> Routes {
+    routes_with_queue(fake, JobQueue::new(2, 8, 8)).await
+}
+
+pub async fn routes_with_queue(fake: &Arc<FakeNargo>, queue: JobQueue) -> Routes {
+    routes_for(fake, &challenges_dir(), queue).await
+}
+
+pub async fn routes_for(fake: &Arc<FakeNargo>, challenges_dir: &Path, queue: JobQueue) -> Routes {
+    let store = ChallengeStore::load(challenges_dir).unwrap_or_else(|errors| {
+        panic!("Failed to load challenges: {:?}", errors.iter().map(|e| e.to_string()))
+    });
+    let workspaces_dir = temp_dir("workspaces");
+    let artifacts_dir = temp_dir("artifacts");
+    let workspaces = WorkspaceManager::new(fake.as_ref(), &workspaces_dir, 0).await;
+    let workspaces = Arc::new(workspaces.unwrap());
+    let cache = Arc::new(ResultCache::new("fake", 64, Duration::from_secs(60)));
+    let artifacts = Arc::new(ArtifactCache::new(&artifacts_dir, "fake", 16));
+    let admin_token = Some(ADMIN_TOKEN.to_string());
+    let filter = server
+        ::routes(
+            Arc::new(store),
+            Arc::new(queue),
+            fake.clone(),
+            workspaces,
+            cache,
+            artifacts,
+            admin_token
+        )
+        .map(Reply::into_response)
+        .boxed();
+    Routes { filter, _dirs: Arc::new(vec![workspaces_dir, artifacts_dir]) }
+}
+
+// Function to get a new directory under the system's temporary directory
+pub fn temp_dir(prefix: &str) -> TempDir {
+    temp_dir_in(&std::env::temp_dir(), prefix)
+}
+
+// Function to make a TempDir somewhere other than the system's temporary directory
+pub fn temp_dir_in(parent: &Path, prefix: &str) -> TempDir {
+    TempDir(parent.join(format!("{}-{}", prefix, uuid::Uuid::new_v4())))
+}
+
+// Function to write a challenge package with the given ID and files into a new temporary
+// challenges directory, returning the directory
+pub fn temp_challenges(id: u32, files: &[(&str, &str)]) -> TempDir {
+    let dir = temp_dir("challenges");
+    add_challenge(&dir, id, files);
+    dir
+}
+
+// Function to write a challenge package into a challenges directory, returning the package's
+// directory. The package is valid unless the given files, written last, replace its
+// challenge.toml, description.md or test case with invalid ones.
+pub fn add_challenge(dir: &Path, id: u32, files: &[(&str, &str)]) -> PathBuf {
+    let package = dir.join(format!("{:02}-test", id));
+    std::fs::create_dir_all(package.join("tests")).unwrap();
+    std::fs::write(package.join("challenge.toml"), metadata(id)).unwrap();
+    std::fs::write(package.join("description.md"), "A challenge for tests").unwrap();
+    let test_case = "#[test]\nfn hidden_test() {\n    main();\n}\n";
+    std::fs::write(package.join("tests/hidden_test.nr"), test_case).unwrap();
+    for (path, contents) in files {
+        let path = package.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+    package
+}
+
+// Function to get the contents of a valid challenge.toml for the given ID, to which tests can add
+// fields
+pub fn metadata(id: u32) -> String {
+    format!("id = {}\ntitle = \"Test\"\ntrack = \"Testing\"\ndifficulty = \"beginner\"\n", id)
+}
+
+// Function to build the body of an execution request
+pub fn execution(challenge_id: u32, code: &str, prover_inputs: &[(&str, &str)]) -> Value {
+    let prover_inputs: serde_json::Map<String, Value> = prover_inputs
+        .iter()
+        .map(|(name, value)| (name.to_string(), Value::String(value.to_string())))
+        .collect();
+    serde_json::json!({
+        "challenge_id": challenge_id,
+        "code": code,
+        "prover_inputs": prover_inputs,
+    })
+}
+
+pub async fn post(routes: &Routes, path: &str, body: &Value) -> Response<Bytes> {
+    warp::test::request().method("POST").path(path).json(body).reply(&routes.filter).await
+}
+
+pub async fn get(routes: &Routes, path: &str) -> Response<Bytes> {
+    warp::test::request().method("GET").path(path).reply(&routes.filter).await
+}
+
+pub async fn delete(routes: &Routes, path: &str) -> Response<Bytes> {
+    warp::test::request().method("DELETE").path(path).reply(&routes.filter).await
+}
+
+pub fn json(response: &Response<Bytes>) -> Value {
+    serde_json::from_slice(response.body()).unwrap_or_else(|e| {
+        panic!("Response is not JSON ({}): {}", e, String::from_utf8_lossy(response.body()))
+    })
+}
+
+// Function to get the names of the commands the fake ran, in order
+pub fn commands(fake: &FakeNargo) -> Vec<&'static str> {
+    fake.calls()
+        .iter()
+        .map(|call| call.command.name())
+        .collect()
+}
+
+// Function to wait until the fake has started running a command with the given name
+pub async fn wait_for_command(fake: &FakeNargo, name: &str) {
+    for _ in 0..500 {
+        if commands(fake).contains(&name) {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("nargo {} was never run, ran {:?}", name, commands(fake));
+}
+
+// Function to check that the project directory of every command the fake ran has been removed.
+// Commands run in the `project` sub-directory of a request's project directory.
+pub fn assert_cleaned_up(fake: &FakeNargo) {
+    let projects: Vec<_> = fake
+        .calls()
+        .into_iter()
+        .filter(|call| !matches!(call.command, NargoCommand::New(_)))
+        .map(|call| call.dir.parent().unwrap().to_path_buf())
+        .collect();
+    assert!(!projects.is_empty(), "no project was created");
+    for dir in projects {
+        wait_until_removed(&dir);
+    }
+}
+
+// Function to wait for a project directory to be removed, which happens on a blocking thread
+// once its workspace is dropped
+pub fn wait_until_removed(dir: &Path) {
+    for _ in 0..200 {
+        if !dir.exists() {
+            return;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    panic!("project directory {:?} was left behind", dir);
+}
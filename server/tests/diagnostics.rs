@@ -0,0 +1,213 @@
+mod common;
+
+use common::{ execution, json, post, routes, routes_for, temp_challenges };
+use common::fake::{ FakeNargo, FakeRun };
+use server::diagnostics::{ self, Label, Severity };
+use server::queue::JobQueue;
+use server::sources::{ Origin, Sources };
+use std::sync::Arc;
+
+const CODE: &str = "fn main(x : Field, y : pub Field) {\n    assert(x != z);\n}\n";
+
+const UNKNOWN_VARIABLE: &str = "\
+error: cannot find `z` in this scope
+  ┌─ src/main.nr:2:17
+  │
+2 │     assert(x != z);
+  │                 - not found in this scope
+  │
+
+";
+
+const UNUSED_VARIABLE: &str = "\
+warning: unused variable y
+  ┌─ src/main.nr:1:20
+  │
+1 │ fn main(x : Field, y : pub Field) {
+  │                    - unused variable
+  │
+
+";
+
+const IN_HIDDEN_TEST: &str = "\
+error: Expected type Field, found type bool
+  ┌─ /srv/noir_projects/1234/project/src/hidden_tests.nr:6:12
+  │
+6 │     assert(flag == x);
+  │            ----    - Field
+  │            │
+  │            bool
+  │
+  = Call stack:
+    1. /srv/noir_projects/1234/project/src/hidden_tests.nr:6:12
+    2. src/main.nr:2:5
+
+Aborting due to 1 previous error
+";
+
+const HIDDEN_TESTS: &str = "\
+#[test]
+fn hidden_test_main() {
+    let flag = true;
+    assert(flag == x);
+    main(1, 2);
+}";
+
+#[test]
+fn parses_errors_and_warnings_with_their_spans() {
+    let aborting = "Aborting due to 1 previous error\n";
+    let output = format!("{}{}{}", UNKNOWN_VARIABLE, UNUSED_VARIABLE, aborting);
+    let parsed = diagnostics::parse(&output);
+
+    assert_eq!(parsed.len(), 2);
+    assert_eq!(parsed[0].severity, Severity::Error);
+    assert_eq!(parsed[0].message, "cannot find `z` in this scope");
+    assert_eq!(parsed[0].file.as_deref(), Some("src/main.nr"));
+    assert_eq!((parsed[0].line, parsed[0].column, parsed[0].length), (Some(2), Some(17), Some(1)));
+    assert_eq!(
+        parsed[0].labels,
+        vec![Label {
+            message: "not found in this scope".to_string(),
+            file: "src/main.nr".to_string(),
+            line: 2,
+            column: 17,
+            length: 1,
+        }]
+    );
+    assert_eq!(parsed[1].severity, Severity::Warning);
+    assert_eq!(parsed[1].message, "unused variable y");
+    assert_eq!((parsed[1].line, parsed[1].column, parsed[1].length), (Some(1), Some(20), Some(1)));
+}
+
+#[test]
+fn parses_several_labels_on_a_line_and_notes() {
+    let parsed = diagnostics::parse(IN_HIDDEN_TEST);
+
+    assert_eq!(parsed.len(), 1);
+    // The server's directories are not given away
+    assert_eq!(parsed[0].file.as_deref(), Some("src/hidden_tests.nr"));
+    assert_eq!(parsed[0].length, Some(4));
+    let labels: Vec<_> = parsed[0].labels
+        .iter()
+        .map(|label| (label.column, label.length, label.message.as_str()))
+        .collect();
+    assert_eq!(labels, vec![(12, 4, "bool"), (20, 1, "Field")]);
+    let call_stack = "Call stack:\n1. /srv/noir_projects/1234/project/src/hidden_tests.nr:6:12\n2. \
+src/main.nr:2:5";
+    assert_eq!(parsed[0].notes, vec![call_stack]);
+}
+
+#[test]
+fn output_without_diagnostics_parses_to_nothing() {
+    let output = "[project] Running 1 test functions\n[project] Testing test_main... ok\n";
+    assert!(diagnostics::parse(output).is_empty());
+    assert!(diagnostics::parse("").is_empty());
+}
+
+#[test]
+fn hidden_tests_get_their_own_module() {
+    let sources = Sources::new(CODE, &[HIDDEN_TESTS.to_string()]);
+
+    assert!(sources.main.starts_with(CODE));
+    assert!(sources.main.ends_with("\nmod hidden_tests;\n"));
+    // Only what the hidden tests use is imported, so there are no unused imports
+    assert!(sources.hidden_tests.starts_with("use crate::{ main };\n"), "{}", sources.hidden_tests);
+    assert!(sources.hidden_tests.contains(HIDDEN_TESTS));
+
+    let map = &sources.map;
+    assert_eq!(map.origin("src/main.nr", 3), Origin::Visible);
+    assert_eq!(map.origin("src/main.nr", 5), Origin::Generated);
+    assert_eq!(map.origin("src/helper.nr", 40), Origin::Visible);
+    assert_eq!(map.origin("src/hidden_tests.nr", 1), Origin::Generated);
+    assert_eq!(map.origin("src/hidden_tests.nr", 6), Origin::HiddenTest("hidden_test_main"));
+}
+
+#[test]
+fn diagnostics_and_output_only_show_the_learners_lines() {
+    let sources = Sources::new(CODE, &[HIDDEN_TESTS.to_string()]);
+    let hidden_warning = "warning: unused variable flag\n  ┌─ src/hidden_tests.nr:4:9\n";
+    let output = format!("{}{}{}", UNKNOWN_VARIABLE, IN_HIDDEN_TEST, hidden_warning);
+    let mut parsed = diagnostics::parse(&output);
+    sources.map.rewrite(&mut parsed);
+
+    // Warnings about hidden code are dropped
+    assert_eq!(parsed.len(), 2);
+    assert_eq!(parsed[0].line, Some(2));
+    assert_eq!(parsed[0].labels.len(), 1);
+    let message = "hidden test `hidden_test_main` failed: Expected type Field, found type bool";
+    assert_eq!(parsed[1].message, message);
+    assert_eq!((parsed[1].file.as_deref(), parsed[1].line), (None, None));
+    assert!(parsed[1].labels.is_empty());
+    let call_stack = "Call stack:\n1. hidden test `hidden_test_main`\n2. src/main.nr:2:5";
+    assert_eq!(parsed[1].notes, vec![call_stack]);
+
+    let redacted = sources.map.redact_output(&output);
+    assert!(!redacted.contains("flag"), "{}", redacted);
+    assert!(!redacted.contains("/srv"), "{}", redacted);
+    assert!(redacted.contains("assert(x != z);"), "{}", redacted);
+    assert!(redacted.contains("error: Expected type Field, found type bool"), "{}", redacted);
+}
+
+#[tokio::test]
+async fn a_failed_check_returns_its_diagnostics() {
+    let fake = Arc::new(FakeNargo::new());
+    fake.on("check", FakeRun::failure(1).stderr(UNKNOWN_VARIABLE));
+    let routes = routes(&fake).await;
+
+    let response = post(&routes, "/execute_check", &execution(1, CODE, &[])).await;
+    assert_eq!(response.status(), 422);
+    let error = json(&response);
+    assert_eq!(error["diagnostics"][0]["message"], "cannot find `z` in this scope");
+    assert_eq!(error["diagnostics"][0]["line"], 2);
+    assert_eq!(error["diagnostics"][0]["column"], 17);
+}
+
+#[tokio::test]
+async fn execute_reports_diagnostics_without_hidden_code() {
+    let fake = Arc::new(FakeNargo::new());
+    let stderr = format!("{}{}", UNKNOWN_VARIABLE, IN_HIDDEN_TEST);
+    fake.on("test", FakeRun::failure(1).stderr(&stderr));
+    let routes = routes(&fake).await;
+
+    let response = post(&routes, "/execute", &execution(1, CODE, &[("x", "1")])).await;
+    assert_eq!(response.status(), 200);
+    let report = json(&response);
+    let diagnostics = report["steps"][0]["diagnostics"].as_array().unwrap().clone();
+    assert_eq!(diagnostics.len(), 2, "{}", report);
+    assert_eq!(diagnostics[0]["line"], 2);
+    assert!(diagnostics[1]["message"].as_str().unwrap().starts_with("hidden test"));
+    assert_eq!(diagnostics[1]["line"], serde_json::Value::Null);
+    assert_eq!(diagnostics[1]["labels"], serde_json::json!([]));
+    let stderr = report["steps"][0]["stderr"].as_str().unwrap();
+    assert!(!stderr.contains("flag"), "{}", stderr);
+}
+
+#[tokio::test]
+async fn execute_test_reports_diagnostics() {
+    let fake = Arc::new(FakeNargo::new());
+    fake.on("test", FakeRun::failure(1).stderr(UNKNOWN_VARIABLE));
+    let routes = routes(&fake).await;
+
+    let code = format!("{}\n#[test]\nfn test_main() {{\n    main(1, 2);\n}}\n", CODE);
+    let response = post(&routes, "/execute_test", &execution(1, &code, &[])).await;
+    let report = json(&response);
+    assert_eq!(report["diagnostics"][0]["severity"], "error", "{}", report);
+    assert_eq!(report["diagnostics"][0]["length"], 1);
+}
+
+#[tokio::test]
+async fn failed_vectors_leave_out_the_hidden_tests() {
+    let vectors = "[[vector]]\nname = \"proves\"\nexpect = \"success\"\ninputs = { x = \"9\" }\n";
+    let dir = temp_challenges(7, &[("vectors.toml", vectors)]);
+    let fake = Arc::new(FakeNargo::new());
+    fake.on_matching("prove", "x = \"9\"", FakeRun::failure(1).stderr(IN_HIDDEN_TEST));
+    let routes = routes_for(&fake, &dir, JobQueue::new(1, 8, 8)).await;
+
+    let response = post(&routes, "/execute", &execution(7, CODE, &[("x", "1")])).await;
+    let report = json(&response);
+    let detail = report["vectors"][0]["detail"].as_str().unwrap();
+    assert!(detail.starts_with("Expected a proof to be generated"), "{}", report);
+    assert!(detail.contains("error: Expected type Field, found type bool"), "{}", detail);
+    assert!(!detail.contains("flag"), "{}", detail);
+    assert!(!detail.contains("hidden_tests.nr"), "{}", detail);
+}
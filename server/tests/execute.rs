@@ -0,0 +1,455 @@
+mod common;
+
+use common::{
+    assert_cleaned_up,
+    commands,
+    execution,
+    json,
+    post,
+    routes,
+    routes_for,
+    temp_challenges,
+};
+use common::fake::{ FakeNargo, FakeRun };
+use server::queue::JobQueue;
+use server::sandbox::LimitKind;
+use server::toolchain::NargoCommand;
+use std::sync::Arc;
+
+const SOLUTION: &str = "fn main(x : Field, y : pub Field) {\n    assert(x != y);\n}\n";
+
+// Function to script the fake like nargo on challenge 1's reference solution: the hidden test
+// passes, and proving fails when x and y are equal
+fn solved_challenge() -> Arc<FakeNargo> {
+    let fake = Arc::new(FakeNargo::new());
+    fake.on_matching(
+        "test",
+        "hidden_test_main",
+        FakeRun::success().stdout(
+            "[project] Running 1 test functions\n[project] Testing hidden_test_main... ok\n"
+        )
+    );
+    let failed = FakeRun::failure(1).stderr("error: Failed constraint\n");
+    fake.on_matching("prove", "x = \"5\"", failed);
+    fake
+}
+
+#[tokio::test]
+async fn execute_passes_when_every_step_and_vector_passes() {
+    let fake = solved_challenge();
+    let routes = routes(&fake).await;
+
+    let body = execution(1, SOLUTION, &[("x", "1"), ("y", "2")]);
+    let response = post(&routes, "/execute", &body).await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["X-Queue-Position"], "0");
+    let report = json(&response);
+    assert_eq!(report["verdict"], "passed", "{}", report);
+    let steps: Vec<_> = report["steps"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|s| (s["name"].as_str().unwrap(), s["status"].as_str().unwrap()))
+        .collect();
+    assert_eq!(
+        steps,
+        vec![("test", "passed"), ("check", "passed"), ("prove", "passed"), ("verify", "passed")]
+    );
+    // The hidden test was appended to the learner's code
+    assert!(report["steps"][0]["stdout"].as_str().unwrap().contains("hidden_test_main"));
+    let vectors = report["vectors"].as_array().unwrap();
+    assert_eq!(vectors.len(), 2);
+    assert!(vectors.iter().all(|v| v["passed"] == true), "{}", report);
+
+    assert_eq!(commands(&fake), vec!["new", "test", "check", "prove", "verify", "prove", "prove"]);
+    let calls = fake.calls();
+    assert_eq!(calls[3].command, NargoCommand::Prove("proof-1".to_string()));
+    assert_eq!(calls[5].command, NargoCommand::Prove("vector-1".to_string()));
+    assert_cleaned_up(&fake);
+}
+
+#[tokio::test]
+async fn changing_only_the_inputs_reuses_the_compiled_circuit() {
+    let fake = solved_challenge();
+    let routes = routes(&fake).await;
+
+    let body = execution(1, SOLUTION, &[("x", "1"), ("y", "2")]);
+    let first = json(&post(&routes, "/execute", &body).await);
+    assert_eq!(first["verdict"], "passed", "{}", first);
+    assert_eq!(first["steps"][0]["cached"], false);
+    // Proving compiles the program, which verifying and the vectors then reuse
+    assert_eq!(fake.compiles(), 1);
+    let ran = fake.calls().len();
+
+    let body = execution(1, SOLUTION, &[("x", "3"), ("y", "4")]);
+    let response = post(&routes, "/execute", &body).await;
+    assert_eq!(response.headers()["X-Cache"], "miss");
+    let second = json(&response);
+    assert_eq!(second["verdict"], "passed", "{}", second);
+    let cached: Vec<_> = second["steps"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|s| (s["name"].as_str().unwrap(), s["cached"].as_bool().unwrap()))
+        .collect();
+    assert_eq!(cached, vec![("test", true), ("check", true), ("prove", false), ("verify", false)]);
+    let names: Vec<_> = fake.calls()[ran..]
+        .iter()
+        .map(|call| call.command.name())
+        .collect();
+    assert_eq!(names, vec!["prove", "verify", "prove", "prove"]);
+    assert_eq!(fake.compiles(), 1);
+
+    // Changing the code compiles it again
+    let code = format!("{}// edited\n", SOLUTION);
+    let body = execution(1, &code, &[("x", "3"), ("y", "4")]);
+    let third = json(&post(&routes, "/execute", &body).await);
+    assert_eq!(third["steps"][0]["cached"], false);
+    assert_eq!(fake.compiles(), 2);
+    assert_cleaned_up(&fake);
+}
+
+#[tokio::test]
+async fn execute_skips_the_remaining_steps_once_one_fails() {
+    let fake = Arc::new(FakeNargo::new());
+    let failed = FakeRun::failure(1).stderr("\u{1b}[31merror\u{1b}[0m: Failed constraint\n");
+    fake.on("test", failed);
+    let routes = routes(&fake).await;
+
+    let body = execution(1, SOLUTION, &[("x", "1"), ("y", "2")]);
+    let response = post(&routes, "/execute", &body).await;
+    assert_eq!(response.status(), 200);
+    let report = json(&response);
+    assert_eq!(report["verdict"], "failed");
+    assert_eq!(report["message"], "Failed at step `test`");
+    let statuses: Vec<_> = report["steps"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|s| s["status"].as_str().unwrap())
+        .collect();
+    assert_eq!(statuses, vec!["failed", "skipped", "skipped", "skipped"]);
+    // Terminal colour codes are removed
+    assert_eq!(report["steps"][0]["stderr"], "error: Failed constraint");
+    assert_eq!(report["vectors"], serde_json::json!([]));
+    assert_eq!(commands(&fake), vec!["new", "test"]);
+    assert_cleaned_up(&fake);
+}
+
+#[tokio::test]
+async fn execute_reports_failed_test_vectors() {
+    // Proving never fails, so the vector that expects it to fail does not pass
+    let fake = Arc::new(FakeNargo::new());
+    let routes = routes(&fake).await;
+
+    let body = execution(1, SOLUTION, &[("x", "1"), ("y", "2")]);
+    let response = post(&routes, "/execute", &body).await;
+    let report = json(&response);
+    assert_eq!(report["verdict"], "failed");
+    assert_eq!(report["message"], "1 of 2 test vectors failed");
+    assert_eq!(report["vectors"][1]["name"], "equal values");
+    assert_eq!(report["vectors"][1]["passed"], false);
+    assert_cleaned_up(&fake);
+}
+
+#[tokio::test]
+async fn execute_checks_the_return_value_of_vectors() {
+    let vectors = r#"
+[[vector]]
+name = "returns three"
+expect = "success"
+return_value = "3"
+inputs = { x = "1" }
+
+[[vector]]
+name = "returns four"
+expect = "success"
+return_value = "4"
+inputs = { x = "2" }
+"#;
+    let dir = temp_challenges(7, &[("vectors.toml", vectors)]);
+    let fake = Arc::new(FakeNargo::new());
+    fake.on("prove", FakeRun::success().write_file("Verifier.toml", "return = \"0x03\"\n"));
+    let routes = routes_for(&fake, &dir, JobQueue::new(1, 8, 8)).await;
+
+    let code = "fn main(x: Field) -> pub Field { x + 2 }";
+    let response = post(&routes, "/execute", &execution(7, code, &[("x", "1")])).await;
+    let report = json(&response);
+    assert_eq!(report["vectors"][0]["passed"], true, "{}", report);
+    assert_eq!(report["vectors"][1]["passed"], false, "{}", report);
+    let detail = "Expected main to return \"4\", but it returned \"0x03\"";
+    assert_eq!(report["vectors"][1]["detail"], detail);
+    assert_cleaned_up(&fake);
+}
+
+#[tokio::test]
+async fn negative_return_values_stand_for_field_elements() {
+    let vectors = r#"
+[[vector]]
+name = "returns minus one"
+expect = "success"
+return_value = -1
+inputs = { x = "1" }
+"#;
+    let dir = temp_challenges(7, &[("vectors.toml", vectors)]);
+    let fake = Arc::new(FakeNargo::new());
+    // The field order minus one
+    let minus_one = "return = \"0x30644e72e131a029b85045b68181585d\
+                     2833e84879b9709143e1f593f0000000\"\n";
+    fake.on("prove", FakeRun::success().write_file("Verifier.toml", minus_one));
+    let routes = routes_for(&fake, &dir, JobQueue::new(1, 8, 8)).await;
+
+    let code = "fn main(x: Field) -> pub Field { 0 - x }";
+    let response = post(&routes, "/execute", &execution(7, code, &[("x", "1")])).await;
+    let report = json(&response);
+    assert_eq!(report["vectors"][0]["passed"], true, "{}", report);
+    assert_cleaned_up(&fake);
+}
+
+#[tokio::test]
+async fn failure_vectors_must_fail_a_constraint() {
+    let vectors = r#"
+[[vector]]
+name = "rejects equal inputs"
+expect = "failure"
+inputs = { x = "5", y = "5" }
+"#;
+    let dir = temp_challenges(7, &[("vectors.toml", vectors)]);
+    let fake = Arc::new(FakeNargo::new());
+    let mismatch = "error: The parameters in main do not match the inputs in Prover.toml\n";
+    fake.on_matching("prove", "x = \"5\"", FakeRun::failure(1).stderr(mismatch));
+    let routes = routes_for(&fake, &dir, JobQueue::new(1, 8, 8)).await;
+
+    let code = "fn main(x: Field, z: Field) {\n    assert(x != z);\n}\n";
+    let response = post(&routes, "/execute", &execution(7, code, &[("x", "1"), ("z", "2")])).await;
+    let report = json(&response);
+    assert_eq!(report["vectors"][0]["passed"], false, "{}", report);
+    let detail = "Expected a constraint to fail, but proving failed for another reason: \
+                  error: The parameters in main do not match the inputs in Prover.toml";
+    assert_eq!(report["vectors"][0]["detail"], detail);
+    assert_cleaned_up(&fake);
+}
+
+#[tokio::test]
+async fn vectors_can_have_struct_inputs() {
+    // `point` sorts before `scale`, but a table has to come after the plain values in TOML
+    let vectors = r#"
+[[vector]]
+name = "scales a point"
+expect = "success"
+
+[vector.inputs]
+point = { x = "1", y = "2" }
+scale = "3"
+"#;
+    let dir = temp_challenges(7, &[("vectors.toml", vectors)]);
+    let fake = Arc::new(FakeNargo::new());
+    fake.on_matching("prove", "[point]", FakeRun::failure(1).stderr("error: Failed assertion"));
+    fake.on_matching("prove", "scale = \"3\"\n\n[point]\nx = \"1\"\ny = \"2\"", FakeRun::success());
+    let routes = routes_for(&fake, &dir, JobQueue::new(1, 8, 8)).await;
+
+    let code = "struct Point { x: Field, y: Field }\n\nfn main(point: Point, scale: Field) {}";
+    let response = post(&routes, "/execute", &execution(7, code, &[("scale", "1")])).await;
+    let report = json(&response);
+    assert_eq!(report["vectors"][0]["passed"], true, "{}", report);
+    assert_cleaned_up(&fake);
+}
+
+#[tokio::test]
+async fn execute_rejects_an_unknown_challenge() {
+    let fake = Arc::new(FakeNargo::new());
+    let routes = routes(&fake).await;
+
+    let response = post(&routes, "/execute", &execution(99, SOLUTION, &[])).await;
+    assert_eq!(response.status(), 404);
+    assert_eq!(json(&response)["code"], "challenge_not_found");
+    assert_eq!(json(&response)["message"], "No challenge found with ID 99");
+    assert_eq!(commands(&fake), vec!["new"]);
+}
+
+#[tokio::test]
+async fn execute_rejects_a_malformed_body() {
+    let fake = Arc::new(FakeNargo::new());
+    let routes = routes(&fake).await;
+
+    let response = post(&routes, "/execute", &serde_json::json!({ "code": "fn main() {}" })).await;
+    assert_eq!(response.status(), 400);
+    assert_eq!(json(&response)["code"], "bad_request");
+    assert_eq!(commands(&fake), vec!["new"]);
+}
+
+#[tokio::test]
+async fn execute_writes_typed_prover_inputs() {
+    let fake = Arc::new(FakeNargo::new());
+    let prover_toml = "\
+big = \"18446744073709551615\"
+flag = true
+hash_path = [\"0x1f\", \"2\"]
+x = 1
+
+[point]
+x = -3
+y = \"0x2\"
+";
+    fake.on_matching("prove", prover_toml, FakeRun::failure(1).stderr("error: typed inputs\n"));
+    let routes = routes(&fake).await;
+
+    let mut body = execution(1, SOLUTION, &[]);
+    body["prover_inputs"] = serde_json::json!({
+        "x": 1,
+        "flag": true,
+        "big": 18446744073709551615u64,
+        "hash_path": ["0x1f", 2],
+        "point": { "x": -3, "y": "0x2" },
+    });
+    let report = json(&post(&routes, "/execute", &body).await);
+    assert_eq!(report["steps"][2]["stderr"], "error: typed inputs", "{}", report);
+}
+
+#[tokio::test]
+async fn execute_rejects_prover_inputs_toml_cannot_represent() {
+    let fake = Arc::new(FakeNargo::new());
+    let routes = routes(&fake).await;
+
+    let cases = [
+        (serde_json::json!({ "x": null }), "Prover input `x` is null"),
+        (serde_json::json!({ "point": { "x": 1.5 } }), "Prover input `point.x` is not a whole"),
+        (serde_json::json!({ "path": [1, true] }), "Prover input `path` mixes elements"),
+        (serde_json::json!({ "path": [{ "a b": 1 }] }), "Prover input `path[0].a b` is not"),
+    ];
+    for (prover_inputs, message) in cases {
+        let mut body = execution(1, SOLUTION, &[]);
+        body["prover_inputs"] = prover_inputs;
+        let response = post(&routes, "/execute", &body).await;
+        assert_eq!(response.status(), 400);
+        let error = json(&response);
+        assert_eq!(error["code"], "bad_request");
+        assert!(error["message"].as_str().unwrap().starts_with(message), "{}", error);
+    }
+    assert_eq!(commands(&fake), vec!["new"]);
+}
+
+#[tokio::test]
+async fn code_that_runs_out_of_time_is_a_timeout() {
+    let fake = Arc::new(FakeNargo::new());
+    fake.on("prove", FakeRun::limit(LimitKind::WallClock));
+    let routes = routes(&fake).await;
+
+    let body = execution(1, SOLUTION, &[("x", "1"), ("y", "2")]);
+    let response = post(&routes, "/execute", &body).await;
+    assert_eq!(response.status(), 408);
+    assert_eq!(json(&response)["code"], "time_limit");
+    assert!(json(&response)["message"].as_str().unwrap().contains("seconds to run"));
+    assert_cleaned_up(&fake);
+}
+
+#[tokio::test]
+async fn code_that_runs_out_of_memory_is_too_large() {
+    let fake = Arc::new(FakeNargo::new());
+    fake.on("test", FakeRun::limit(LimitKind::Memory));
+    let routes = routes(&fake).await;
+
+    let body = execution(1, SOLUTION, &[("x", "1"), ("y", "2")]);
+    let response = post(&routes, "/execute", &body).await;
+    assert_eq!(response.status(), 413);
+    assert_eq!(json(&response)["code"], "memory_limit");
+    assert!(json(&response)["message"].as_str().unwrap().contains("MB of memory"));
+    assert_cleaned_up(&fake);
+}
+
+#[tokio::test]
+async fn execute_test_reports_each_test_without_the_hidden_tests() {
+    let fake = Arc::new(FakeNargo::new());
+    fake.on(
+        "test",
+        FakeRun::failure(1)
+            .stdout(
+                "[project] Running 2 test functions\n\
+                 [project] Testing test_a... ok\n\
+                 [project] Testing test_b... \n0x01\nFAIL\n"
+            )
+            .stderr("error: Failed constraint\n")
+    );
+    fake.on_matching("test", "hidden_test_main", FakeRun::failure(2));
+    let routes = routes(&fake).await;
+
+    let code = format!("{}\n#[test]\nfn test_a() {{}}\n#[test]\nfn test_b() {{}}\n", SOLUTION);
+    let response = post(&routes, "/execute_test", &execution(1, &code, &[])).await;
+    assert_eq!(response.status(), 200);
+    let report = json(&response);
+    assert_eq!(report["passed"], false);
+    assert_eq!(report["message"], "1 of 2 tests failed");
+    assert_eq!(report["tests"][0]["name"], "test_a");
+    assert_eq!(report["tests"][0]["status"], "passed");
+    assert_eq!(report["tests"][1]["status"], "failed");
+    assert_eq!(report["tests"][1]["output"], serde_json::json!(["0x01"]));
+    assert_eq!(report["tests"][1]["failure_message"], "error: Failed constraint");
+    assert_cleaned_up(&fake);
+}
+
+#[tokio::test]
+async fn execute_test_runs_only_the_named_tests() {
+    let fake = Arc::new(FakeNargo::new());
+    let routes = routes(&fake).await;
+
+    let mut body = execution(1, "#[test]\nfn test_a() {}\n", &[]);
+    body["test_name"] = "test_a".into();
+    let response = post(&routes, "/execute_test", &body).await;
+    assert_eq!(json(&response)["message"], "No tests matched the given name");
+    assert_eq!(fake.calls()[1].command, NargoCommand::Test(Some("test_a".to_string())));
+    assert_cleaned_up(&fake);
+}
+
+#[tokio::test]
+async fn execute_test_rejects_a_name_that_is_not_a_noir_path() {
+    let fake = Arc::new(FakeNargo::new());
+    let routes = routes(&fake).await;
+
+    for name in ["--show-output", "-h", "test a", "tests::", "1test"] {
+        let mut body = execution(1, "#[test]\nfn test_a() {}\n", &[]);
+        body["test_name"] = name.into();
+        let response = post(&routes, "/execute_test", &body).await;
+        assert_eq!(response.status(), 400, "{}", name);
+        let message = format!("Test name `{}` is not a Noir path", name);
+        assert_eq!(json(&response)["message"], message);
+    }
+    assert_eq!(commands(&fake), vec!["new"]);
+}
+
+#[tokio::test]
+async fn execute_test_without_tests_does_not_run_nargo() {
+    let fake = Arc::new(FakeNargo::new());
+    let routes = routes(&fake).await;
+
+    let response = post(&routes, "/execute_test", &execution(1, SOLUTION, &[])).await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(json(&response)["message"], "There are no tests to run");
+    assert_eq!(commands(&fake), vec!["new"]);
+}
+
+#[tokio::test]
+async fn execute_check_returns_the_prover_inputs() {
+    let fake = Arc::new(FakeNargo::new());
+    let routes = routes(&fake).await;
+
+    let response = post(&routes, "/execute_check", &execution(1, SOLUTION, &[])).await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(json(&response), serde_json::json!({ "x": "", "y": "" }));
+    assert_eq!(commands(&fake), vec!["new", "check"]);
+    assert_cleaned_up(&fake);
+}
+
+#[tokio::test]
+async fn execute_check_fails_when_the_code_does_not_compile() {
+    let fake = Arc::new(FakeNargo::new());
+    fake.on("check", FakeRun::failure(1).stderr("error: unexpected token\n"));
+    let routes = routes(&fake).await;
+
+    let response = post(&routes, "/execute_check", &execution(1, "fn main( {", &[])).await;
+    assert_eq!(response.status(), 422);
+    assert_eq!(json(&response)["code"], "code_error");
+    let message = json(&response)["message"].as_str().unwrap().to_string();
+    assert_eq!(message, "Failed to execute check:\nerror: unexpected token");
+    // The project directory is removed on early returns too
+    assert_cleaned_up(&fake);
+}
@@ -0,0 +1,110 @@
+mod common;
+
+use common::{ temp_dir, temp_dir_in, TempDir };
+use server::isolation;
+use server::sandbox::{ run_limited, LimitKind, RunError };
+use std::path::{ Path, PathBuf };
+use std::sync::OnceLock;
+use tokio::process::Command;
+
+// Function to configure isolation before the first command runs, as it is read once, returning
+// the WORKSPACE_DIR it hides. That is outside /tmp and the working directory, which are hidden
+// anyway, and is kept between test runs as every run needs it to exist. The process limit is
+// left to tests/sandbox.rs, so these tests do not need cgroups.
+async fn configure() -> &'static Path {
+    static WORKSPACES: OnceLock<PathBuf> = OnceLock::new();
+    let workspaces = WORKSPACES.get_or_init(|| {
+        let workspaces = PathBuf::from("/var/tmp/noir-server-isolation-tests");
+        std::fs::create_dir_all(&workspaces).unwrap();
+        std::env::set_var("WORKSPACE_DIR", &workspaces);
+        std::env::set_var("NARGO_ISOLATION", "on");
+        std::env::set_var("NARGO_MAX_PROCESSES", "0");
+        workspaces
+    });
+    isolation::check().await.expect("nargo cannot be isolated on this host");
+    workspaces
+}
+
+// Function to run a shell script isolated in a project directory, a new one if dir is None
+async fn run_in(
+    script: &str,
+    dir: Option<TempDir>
+) -> (Result<std::process::Output, RunError>, TempDir) {
+    let dir = dir.unwrap_or_else(|| temp_dir("isolated"));
+    std::fs::create_dir_all(&*dir).unwrap();
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(script).current_dir(&*dir);
+    (run_limited(command, None).await, dir)
+}
+
+async fn run(script: &str) -> (Result<std::process::Output, RunError>, TempDir) {
+    run_in(script, None).await
+}
+
+#[tokio::test]
+#[ignore = "needs unprivileged user namespaces, run with --ignored"]
+async fn a_run_cannot_signal_processes_outside_it() {
+    configure().await;
+    let mut outside = std::process::Command::new("sleep").arg("30").spawn().unwrap();
+
+    // Signalling every process it can see must not reach the server or anything else
+    let script = format!("kill -9 -1; kill -9 {}; echo $$", outside.id());
+    let (output, _dir) = run(&script).await;
+    let output = output.unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    // The shell is the first process after the namespace's PID 1
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "2\n");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No such process"));
+    assert!(outside.try_wait().unwrap().is_none());
+    outside.kill().unwrap();
+    outside.wait().unwrap();
+}
+
+#[tokio::test]
+#[ignore = "needs unprivileged user namespaces, run with --ignored"]
+async fn a_run_can_only_write_its_project_directory() {
+    configure().await;
+    let server_file = std::env::current_dir().unwrap().join("Cargo.toml");
+
+    let script = format!(
+        "echo proof > proof.txt && ls /tmp | wc -l && ! cat {} && ! touch /usr/escaped",
+        server_file.display()
+    );
+    let (output, dir) = run(&script).await;
+    let output = output.unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    // Only the project directory's parent is left in the private /tmp
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n");
+    assert_eq!(std::fs::read_to_string(dir.join("proof.txt")).unwrap(), "proof\n");
+}
+
+#[tokio::test]
+#[ignore = "needs unprivileged user namespaces, run with --ignored"]
+async fn a_run_ends_the_way_nargo_ended() {
+    configure().await;
+
+    let (output, _dir) = run("exit 3").await;
+    assert_eq!(output.unwrap().status.code(), Some(3));
+    let (output, _dir) = run("kill -XFSZ $$").await;
+    assert!(matches!(output, Err(RunError::Limit(e)) if e.kind == LimitKind::FileSize));
+    let (output, _dir) = run("kill -9 $$").await;
+    assert!(matches!(output, Err(RunError::Limit(e)) if e.kind == LimitKind::Killed));
+}
+
+#[tokio::test]
+#[ignore = "needs unprivileged user namespaces, run with --ignored"]
+async fn a_run_cannot_see_other_runs_projects() {
+    let workspaces = configure().await;
+    let other = temp_dir_in(workspaces, "other");
+    std::fs::create_dir_all(&*other).unwrap();
+    std::fs::write(other.join("main.nr"), "fn main() {}").unwrap();
+
+    let project = temp_dir_in(workspaces, "project");
+    let script = format!("ls .. && ! cat {}", other.join("main.nr").display());
+    let (output, project) = run_in(&script, Some(project)).await;
+    let output = output.unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    // Only the run's own project is mounted back into the hidden WORKSPACE_DIR
+    let name = project.file_name().unwrap().to_string_lossy();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), format!("{}\n", name));
+}
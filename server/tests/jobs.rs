@@ -0,0 +1,334 @@
+mod common;
+
+use common::{
+    assert_cleaned_up,
+    commands,
+    delete,
+    execution,
+    get,
+    json,
+    post,
+    routes,
+    routes_with_queue,
+    wait_for_command,
+    Routes,
+};
+use common::fake::{ FakeNargo, FakeRun };
+use serde_json::Value;
+use server::queue::JobQueue;
+use server::sandbox::LimitKind;
+use std::sync::Arc;
+use std::time::Duration;
+
+const CODE: &str = "fn main(x : Field, y : pub Field) {\n    assert(x != y);\n}\n";
+
+// Function to submit a job, returning its ID
+async fn create_job(routes: &Routes, body: &Value) -> String {
+    let response = post(routes, "/jobs", body).await;
+    assert_eq!(response.status(), 202, "{}", String::from_utf8_lossy(response.body()));
+    let job = json(&response);
+    let id = job["id"].as_str().unwrap().to_string();
+    assert_eq!(response.headers()["Location"], format!("/jobs/{}", id));
+    id
+}
+
+// Function to poll a job until it has ended
+async fn wait_for_job(routes: &Routes, id: &str) -> Value {
+    for _ in 0..500 {
+        let job = json(&get(routes, &format!("/jobs/{}", id)).await);
+        if job["status"] != "queued" && job["status"] != "running" {
+            return job;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("job {} never ended", id);
+}
+
+#[tokio::test]
+async fn queue_reports_its_size() {
+    let routes = routes_with_queue(&Arc::new(FakeNargo::new()), JobQueue::new(3, 5, 2)).await;
+
+    let response = get(&routes, "/queue").await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        json(&response),
+        serde_json::json!({ "workers": 3, "running": 0, "queued": 0, "capacity": 5 })
+    );
+}
+
+#[tokio::test]
+async fn a_full_queue_turns_requests_away() {
+    let fake = Arc::new(FakeNargo::new());
+    fake.on("test", FakeRun::success().delay(Duration::from_millis(500)));
+    let routes = routes_with_queue(&fake, JobQueue::new(1, 0, 0)).await;
+
+    let first = tokio::spawn({
+        let routes = routes.clone();
+        async move { post(&routes, "/execute", &execution(1, CODE, &[("x", "1")])).await }
+    });
+    wait_for_command(&fake, "test").await;
+
+    // Identical code would wait for the first result instead of queueing
+    let response = post(&routes, "/execute", &execution(1, CODE, &[("x", "2")])).await;
+    assert_eq!(response.status(), 429);
+    assert!(response.headers()["Retry-After"].to_str().unwrap().parse::<u64>().unwrap() >= 1);
+    assert_eq!(json(&response)["code"], "overloaded");
+    assert!(json(&response)["message"].as_str().unwrap().contains("busy"));
+
+    assert_eq!(first.await.unwrap().status(), 200);
+}
+
+#[tokio::test]
+async fn queued_requests_wait_their_turn() {
+    let fake = Arc::new(FakeNargo::new());
+    fake.on("test", FakeRun::success().delay(Duration::from_millis(300)));
+    let routes = routes_with_queue(&fake, JobQueue::new(1, 4, 4)).await;
+
+    let first = tokio::spawn({
+        let routes = routes.clone();
+        async move { post(&routes, "/execute", &execution(1, CODE, &[("x", "1")])).await }
+    });
+    wait_for_command(&fake, "test").await;
+
+    let second = post(&routes, "/execute", &execution(1, CODE, &[("x", "2")])).await;
+    assert_eq!(second.status(), 200);
+    assert_eq!(second.headers()["X-Queue-Position"], "1");
+    let waited: u64 = second.headers()["X-Queue-Wait-Ms"].to_str().unwrap().parse().unwrap();
+    assert!(waited > 0);
+    assert_eq!(first.await.unwrap().status(), 200);
+}
+
+#[tokio::test]
+async fn a_job_runs_in_the_background() {
+    let fake = Arc::new(FakeNargo::new());
+    let routes = routes(&fake).await;
+
+    let id = create_job(&routes, &execution(1, CODE, &[("x", "1"), ("y", "2")])).await;
+    let job = wait_for_job(&routes, &id).await;
+    assert_eq!(job["status"], "finished", "{}", job);
+    assert_eq!(job["kind"], "execute");
+    assert_eq!(job["steps"].as_array().unwrap().len(), 4);
+    assert_eq!(job["result"]["steps"], job["steps"]);
+    assert!(job["result"]["verdict"].is_string());
+    assert_cleaned_up(&fake);
+}
+
+#[tokio::test]
+async fn a_job_reuses_the_result_of_an_identical_execution() {
+    let fake = Arc::new(FakeNargo::new());
+    let routes = routes(&fake).await;
+    let body = execution(1, CODE, &[("x", "1"), ("y", "2")]);
+
+    let response = post(&routes, "/execute", &body).await;
+    assert_eq!(response.headers()["X-Cache"], "miss");
+    let ran = fake.calls().len();
+
+    let id = create_job(&routes, &body).await;
+    let job = wait_for_job(&routes, &id).await;
+    assert_eq!(job["status"], "finished", "{}", job);
+    assert_eq!(job["cache"], "hit");
+    assert_eq!(job["result"], json(&response));
+    assert_eq!(fake.calls().len(), ran);
+}
+
+#[tokio::test]
+async fn jobs_with_nothing_to_run_do_not_wait_in_the_queue() {
+    let fake = Arc::new(FakeNargo::new());
+    let routes = routes_with_queue(&fake, JobQueue::new(1, 0, 0)).await;
+    let cached = execution(1, CODE, &[("x", "1"), ("y", "2")]);
+    assert_eq!(post(&routes, "/execute", &cached).await.status(), 200);
+
+    // Keep the only worker busy, with no room to queue behind it. Other code is compiled and
+    // tested again rather than reusing the first run's reports.
+    fake.on("test", FakeRun::success().delay(Duration::from_millis(500)));
+    let busy = tokio::spawn({
+        let routes = routes.clone();
+        let code = format!("{}// busy\n", CODE);
+        async move { post(&routes, "/execute", &execution(1, &code, &[("x", "3")])).await }
+    });
+    for _ in 0..500 {
+        if json(&get(&routes, "/queue").await)["running"] == 1 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let response = post(&routes, "/jobs", &execution(1, CODE, &[("x", "4")])).await;
+    assert_eq!(response.status(), 429);
+
+    let job = wait_for_job(&routes, &create_job(&routes, &cached).await).await;
+    assert_eq!(job["status"], "finished", "{}", job);
+    assert_eq!(job["cache"], "hit");
+    let mut no_tests = execution(1, CODE, &[]);
+    no_tests["kind"] = "test".into();
+    let job = wait_for_job(&routes, &create_job(&routes, &no_tests).await).await;
+    assert_eq!(job["result"]["message"], "There are no tests to run");
+
+    assert_eq!(busy.await.unwrap().status(), 200);
+}
+
+#[tokio::test]
+async fn a_test_job_runs_the_learners_tests() {
+    let fake = Arc::new(FakeNargo::new());
+    let routes = routes(&fake).await;
+
+    let mut body = execution(1, CODE, &[]);
+    body["kind"] = "test".into();
+    let id = create_job(&routes, &body).await;
+    let job = wait_for_job(&routes, &id).await;
+    assert_eq!(job["status"], "finished");
+    assert_eq!(job["kind"], "test");
+    assert_eq!(job["result"]["message"], "There are no tests to run");
+}
+
+#[tokio::test]
+async fn a_job_that_hits_a_limit_fails() {
+    let fake = Arc::new(FakeNargo::new());
+    fake.on("check", FakeRun::limit(LimitKind::CpuTime));
+    let routes = routes(&fake).await;
+
+    let id = create_job(&routes, &execution(1, CODE, &[("x", "1")])).await;
+    let job = wait_for_job(&routes, &id).await;
+    assert_eq!(job["status"], "failed");
+    assert_eq!(job["error"]["code"], "cpu_time_limit");
+    assert!(job["error"]["message"].as_str().unwrap().contains("CPU time"), "{}", job);
+    assert_eq!(job["result"], Value::Null);
+    // The step that ran before the limit was hit is still reported
+    assert_eq!(job["steps"][0]["name"], "test");
+    assert_cleaned_up(&fake);
+}
+
+#[tokio::test]
+async fn a_job_for_an_unknown_challenge_is_rejected() {
+    let fake = Arc::new(FakeNargo::new());
+    let routes = routes(&fake).await;
+
+    let response = post(&routes, "/jobs", &execution(99, CODE, &[])).await;
+    assert_eq!(response.status(), 404);
+    assert_eq!(json(&response)["code"], "challenge_not_found");
+    assert_eq!(commands(&fake), vec!["new"]);
+}
+
+#[tokio::test]
+async fn job_events_stream_every_step() {
+    let fake = Arc::new(FakeNargo::new());
+    fake.on("test", FakeRun::success().stdout("[project] Running 0 test functions\n"));
+    fake.on("prove", FakeRun::failure(1).stderr("error: Failed constraint\n"));
+    let routes = routes(&fake).await;
+
+    let id = create_job(&routes, &execution(1, CODE, &[("x", "1")])).await;
+    wait_for_job(&routes, &id).await;
+
+    // The job has ended, so the whole stream is replayed and then closed
+    let response = get(&routes, &format!("/jobs/{}/events", id)).await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+    let body = String::from_utf8_lossy(response.body()).to_string();
+    let events: Vec<&str> = body
+        .lines()
+        .filter_map(|line| line.strip_prefix("event:"))
+        .collect();
+    assert_eq!(
+        events,
+        vec!["step-finish", "step-finish", "step-finish", "step-finish", "done"],
+        "{}",
+        body
+    );
+    assert!(body.contains("\"status\":\"failed\""));
+    assert!(body.contains("\"status\":\"skipped\""));
+}
+
+#[tokio::test]
+async fn job_events_stream_lines_while_running() {
+    let fake = Arc::new(FakeNargo::new());
+    fake.on(
+        "test",
+        FakeRun::success().stdout("first line\n").delay(Duration::from_millis(200))
+    );
+    let routes = routes(&fake).await;
+
+    let id = create_job(&routes, &execution(1, CODE, &[("x", "1")])).await;
+    wait_for_command(&fake, "test").await;
+
+    let response = get(&routes, &format!("/jobs/{}/events", id)).await;
+    let body = String::from_utf8_lossy(response.body()).to_string();
+    let events: Vec<&str> = body
+        .lines()
+        .filter_map(|line| line.strip_prefix("event:"))
+        .collect();
+    assert_eq!(&events[..3], &["step-start", "stdout", "step-finish"], "{}", body);
+    assert_eq!(events.last(), Some(&"done"));
+    assert!(body.contains(r#"{"line":"first line","step":"test"}"#), "{}", body);
+}
+
+#[tokio::test]
+async fn job_events_leave_out_the_hidden_tests() {
+    let fake = Arc::new(FakeNargo::new());
+    let stderr = "error: Failed constraint\n  ┌─ src/hidden_tests.nr:4:5\n  │\n4 │     \
+assert(secret == 42);\n  │     -------------------\n  │\n";
+    fake.on("test", FakeRun::failure(1).stderr(stderr).delay(Duration::from_millis(200)));
+    let routes = routes(&fake).await;
+
+    let id = create_job(&routes, &execution(1, CODE, &[("x", "1")])).await;
+    wait_for_command(&fake, "test").await;
+
+    let response = get(&routes, &format!("/jobs/{}/events", id)).await;
+    let body = String::from_utf8_lossy(response.body()).to_string();
+    let line = r#"{"line":"  ┌─ hidden test `hidden_test_main`","step":"test"}"#;
+    assert!(body.contains(line), "{}", body);
+    assert!(!body.contains("secret"), "{}", body);
+}
+
+#[tokio::test]
+async fn cancelling_a_job_stops_it_and_removes_its_project() {
+    let fake = Arc::new(FakeNargo::new());
+    fake.on("test", FakeRun::success().delay(Duration::from_secs(30)));
+    let routes = routes(&fake).await;
+
+    let id = create_job(&routes, &execution(1, CODE, &[("x", "1")])).await;
+    wait_for_command(&fake, "test").await;
+    assert_eq!(json(&get(&routes, &format!("/jobs/{}", id)).await)["status"], "running");
+
+    let response = tokio::time
+        ::timeout(Duration::from_secs(5), delete(&routes, &format!("/jobs/{}", id))).await
+        .expect("cancelling waited for the job to finish");
+    assert_eq!(response.status(), 200);
+    assert_eq!(json(&response)["status"], "cancelled");
+    assert_cleaned_up(&fake);
+
+    // The cancelled job is kept until it is deleted again
+    assert_eq!(json(&get(&routes, &format!("/jobs/{}", id)).await)["status"], "cancelled");
+    assert_eq!(delete(&routes, &format!("/jobs/{}", id)).await.status(), 200);
+    assert_eq!(get(&routes, &format!("/jobs/{}", id)).await.status(), 404);
+}
+
+#[tokio::test]
+async fn a_queued_job_reports_its_position_and_can_be_cancelled() {
+    let fake = Arc::new(FakeNargo::new());
+    fake.on("test", FakeRun::success().delay(Duration::from_secs(30)));
+    let routes = routes_with_queue(&fake, JobQueue::new(1, 4, 4)).await;
+
+    let running = create_job(&routes, &execution(1, CODE, &[("x", "1")])).await;
+    wait_for_command(&fake, "test").await;
+    let queued = create_job(&routes, &execution(1, CODE, &[("x", "1")])).await;
+
+    let job = json(&get(&routes, &format!("/jobs/{}", queued)).await);
+    assert_eq!(job["status"], "queued");
+    assert_eq!(job["position"], 1);
+    assert_eq!(json(&get(&routes, "/queue").await)["queued"], 1);
+
+    assert_eq!(json(&delete(&routes, &format!("/jobs/{}", queued)).await)["status"], "cancelled");
+    assert_eq!(json(&get(&routes, "/queue").await)["queued"], 0);
+    delete(&routes, &format!("/jobs/{}", running)).await;
+    assert_eq!(json(&get(&routes, "/queue").await)["running"], 0);
+}
+
+#[tokio::test]
+async fn unknown_jobs_are_not_found() {
+    let routes = routes(&Arc::new(FakeNargo::new())).await;
+    let id = uuid::Uuid::new_v4();
+
+    assert_eq!(get(&routes, &format!("/jobs/{}", id)).await.status(), 404);
+    assert_eq!(get(&routes, &format!("/jobs/{}/events", id)).await.status(), 404);
+    assert_eq!(delete(&routes, &format!("/jobs/{}", id)).await.status(), 404);
+    assert_eq!(get(&routes, "/jobs/not-a-uuid").await.status(), 404);
+}
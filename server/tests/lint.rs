@@ -0,0 +1,85 @@
+mod common;
+
+use common::{ challenges_dir, temp_challenges, temp_dir };
+use common::fake::{ FakeNargo, FakeRun };
+use server::challenges::{ load_challenges, Challenge };
+use server::lint::{ check_test_cases, lint_test_cases };
+use server::workspace::WorkspaceManager;
+use std::sync::Arc;
+
+const STARTER: &str = "fn main(x: Field) {\n}\n\nfn double(x: Field) -> Field {\n    0\n}\n";
+
+// Function to load a challenge with the given hidden test cases and the starter code above
+fn load(test_cases: &[(&str, &str)]) -> Arc<Vec<Challenge>> {
+    let mut files = vec![("starter.nr", STARTER)];
+    files.extend_from_slice(test_cases);
+    load_challenges(&temp_challenges(7, &files)).unwrap()
+}
+
+fn lint(test_case: &str) -> Vec<String> {
+    lint_test_cases(&load(&[("tests/hidden_test.nr", test_case)])[0])
+}
+
+#[test]
+fn the_challenges_in_the_repository_are_clean() {
+    for challenge in load_challenges(&challenges_dir()).unwrap().iter() {
+        assert_eq!(lint_test_cases(challenge), Vec::<String>::new(), "challenge {}", challenge.id);
+    }
+}
+
+#[test]
+fn a_test_case_that_calls_learner_code_is_clean() {
+    let test_case = "#[test]\nfn hidden_test() {\n    assert(double(2) == 4);\n}\n";
+    assert_eq!(lint(test_case), Vec::<String>::new());
+}
+
+#[test]
+fn test_cases_must_declare_a_test() {
+    let warnings = lint("fn hidden_check() {\n    main(1);\n}\n");
+    assert_eq!(warnings, vec!["test case 1: does not declare a #[test] function"]);
+}
+
+#[test]
+fn test_cases_must_call_learner_code() {
+    // Neither a comment nor a function of its own counts as calling the learner's code
+    let test_case = "#[test]\nfn hidden_test() {\n    // main(1);\n    hidden_main();\n}\n\n\
+                     fn hidden_main() {\n    assert(1 == 1);\n}\n";
+    let warnings = lint(test_case);
+    let warning = "test case 1: does not call `main` or any other function from the learner's code";
+    assert_eq!(warnings, vec![warning]);
+}
+
+#[test]
+fn test_functions_must_not_collide_with_learner_code() {
+    let warnings = lint("#[test]\nfn double() {\n    main(1);\n}\n");
+    assert_eq!(warnings, vec!["test case 1: function `double` collides with learner code"]);
+}
+
+#[test]
+fn test_functions_must_carry_the_hidden_prefix() {
+    let warnings = lint("#[test]\nfn check_main() {\n    main(1);\n}\n");
+    assert_eq!(warnings, vec!["test case 1: function `check_main` should start with `hidden_`"]);
+}
+
+#[test]
+fn test_functions_must_be_declared_once() {
+    let test_case = "#[test]\nfn hidden_test() {\n    main(1);\n}\n";
+    let challenges = load(&[("tests/hidden_test.nr", test_case), ("tests/more.nr", test_case)]);
+
+    let warnings = lint_test_cases(&challenges[0]);
+    assert_eq!(warnings, vec!["test case 2: function `hidden_test` is declared more than once"]);
+}
+
+#[tokio::test]
+async fn test_cases_that_do_not_compile_are_reported() {
+    let test_case = "#[test]\nfn hidden_test() {\n    main(1, 2);\n}\n";
+    let challenges = load(&[("tests/hidden_test.nr", test_case)]);
+    let fake = FakeNargo::new();
+    fake.on("check", FakeRun::failure(1).stderr("error: Function expects 1 parameter\n"));
+    let root = temp_dir("workspaces");
+    let workspaces = WorkspaceManager::new(&fake, &root, 0).await.unwrap();
+
+    let warnings = check_test_cases(&fake, &workspaces, &challenges[0]).await;
+    let warning = "test case 1: nargo check failed: error: Function expects 1 parameter";
+    assert_eq!(warnings, vec![warning]);
+}
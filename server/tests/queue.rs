@@ -0,0 +1,54 @@
+use server::queue::JobQueue;
+use std::net::{ IpAddr, Ipv4Addr };
+use std::sync::{ Arc, Mutex };
+
+fn client(n: u8) -> IpAddr {
+    IpAddr::V4(Ipv4Addr::new(10, 0, 0, n))
+}
+
+#[tokio::test]
+async fn waiting_clients_take_turns() {
+    let queue = Arc::new(JobQueue::new(1, 16, 0));
+    let running = queue.acquire(client(1)).await.unwrap();
+
+    // Client 1 queues three jobs before clients 2 and 3 queue one each
+    let queued = [1, 1, 1, 2, 3].map(|n| (n, queue.enqueue(client(n)).unwrap()));
+    let positions: Vec<_> = queued
+        .iter()
+        .map(|(_, ticket)| queue.position(ticket.id).unwrap())
+        .collect();
+    assert_eq!(positions, vec![1, 4, 5, 2, 3]);
+
+    let started = Arc::new(Mutex::new(vec![]));
+    let mut tasks = vec![];
+    for (n, ticket) in queued {
+        let started = Arc::clone(&started);
+        tasks.push(
+            tokio::spawn(async move {
+                let _permit = ticket.wait().await;
+                started.lock().unwrap().push(n);
+            })
+        );
+    }
+    drop(running);
+    for task in tasks {
+        task.await.unwrap();
+    }
+    assert_eq!(*started.lock().unwrap(), vec![1, 2, 3, 1, 1]);
+}
+
+#[tokio::test]
+async fn one_client_cannot_fill_the_queue() {
+    let queue = Arc::new(JobQueue::new(1, 16, 2));
+    let _running = queue.acquire(client(1)).await.unwrap();
+    let _first = queue.enqueue(client(1)).unwrap();
+    let _second = queue.enqueue(client(1)).unwrap();
+
+    let full = queue.enqueue(client(1)).err().unwrap();
+    assert_eq!(full.message, "You already have too many jobs waiting, please try again shortly");
+    assert!(full.retry_after.as_secs() >= 1);
+    // Other clients still get a place
+    let other = queue.enqueue(client(2)).unwrap();
+    assert_eq!(queue.position(other.id), Some(2));
+    assert_eq!(queue.status().queued, 3);
+}
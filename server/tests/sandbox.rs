@@ -0,0 +1,51 @@
+use server::cgroups;
+use server::sandbox::{ run_limited, LimitKind, RunError };
+use std::sync::Once;
+use tokio::process::Command;
+
+// Function to configure the limits before the first command runs, as they are read once
+fn configure() {
+    static CONFIGURE: Once = Once::new();
+    CONFIGURE.call_once(|| {
+        std::env::set_var("NARGO_ISOLATION", "off");
+        std::env::set_var("NARGO_MAX_PROCESSES", "8");
+    });
+}
+
+async fn run(script: &str) -> Result<std::process::Output, RunError> {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(script);
+    run_limited(command, None).await
+}
+
+fn limit_kind(result: Result<std::process::Output, RunError>) -> Option<LimitKind> {
+    match result {
+        Err(RunError::Limit(e)) => Some(e.kind),
+        _ => None,
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs a writable cgroup with the pids controller, run with --ignored"]
+async fn a_kill_without_running_out_of_memory_is_reported_as_a_kill() {
+    configure();
+    cgroups::check().expect("nargo's processes cannot be limited on this host");
+
+    assert_eq!(limit_kind(run("kill -9 $$").await), Some(LimitKind::Killed));
+}
+
+#[tokio::test]
+#[ignore = "needs a writable cgroup with the pids controller, run with --ignored"]
+async fn each_run_has_its_own_process_limit() {
+    configure();
+    cgroups::check().expect("nargo's processes cannot be limited on this host");
+
+    // Together these use more processes than one run may, which is fine
+    let five = "for i in 1 2 3 4 5; do sleep 0.3 & done; wait";
+    let (first, second) = tokio::join!(run(five), run(five));
+    assert!(first.unwrap().status.success());
+    assert!(second.unwrap().status.success());
+
+    let twenty = "for i in $(seq 20); do sleep 0.3 & done; wait";
+    assert_eq!(limit_kind(run(twenty).await), Some(LimitKind::Processes));
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use common::temp_dir;
+use common::fake::{ FakeNargo, FakeRun };
+use server::template::ProjectTemplate;
+use server::toolchain::NargoCommand;
+
+#[tokio::test]
+async fn the_template_is_made_once_and_copied_into_each_project() {
+    let fake = FakeNargo::new();
+    fake.on("new", FakeRun::success().write_file("project/src/lib/util.nr", "fn util() {}\n"));
+    let root = temp_dir("templates");
+    let template = ProjectTemplate::create(&fake, &root).await.unwrap();
+
+    let first = temp_dir("project");
+    let second = temp_dir("project");
+    for dir in [&first, &second] {
+        std::fs::create_dir_all(&**dir).unwrap();
+        template.copy_to(dir).await.unwrap();
+    }
+    // Each copy is the whole project, nested directories included, and owns its files
+    std::fs::write(first.join("project/src/main.nr"), "fn main(x: Field) {}\n").unwrap();
+    let copied = |path: &str| std::fs::read_to_string(second.join("project").join(path)).unwrap();
+    assert_eq!(copied("Nargo.toml"), "[package]\nname = \"project\"\ntype = \"bin\"\n");
+    assert_eq!(copied("src/main.nr"), "fn main() {}\n");
+    assert_eq!(copied("src/lib/util.nr"), "fn util() {}\n");
+
+    let news = fake
+        .calls()
+        .iter()
+        .filter(|call| matches!(call.command, NargoCommand::New(_)))
+        .count();
+    assert_eq!(news, 1);
+
+    let dir = template.dir().to_path_buf();
+    assert!(dir.starts_with(&*root));
+    drop(template);
+    assert!(!dir.exists());
+}
+
+#[tokio::test]
+async fn a_template_that_fails_to_build_leaves_nothing_behind() {
+    let fake = FakeNargo::new();
+    fake.on("new", FakeRun::failure(1).stderr("error: Invalid package name\n"));
+    let root = temp_dir("templates");
+
+    let error = ProjectTemplate::create(&fake, &root).await.err().unwrap();
+    assert_eq!(error, "nargo new failed: error: Invalid package name");
+    assert_eq!(std::fs::read_dir(&*root).unwrap().count(), 0);
+}
@@ -0,0 +1,37 @@
+mod common;
+
+use common::{ challenges_dir, temp_dir };
+use common::fake::{ FakeNargo, FakeRun };
+use server::challenges::{ load_challenges, Expectation };
+use server::validate::validate_challenges;
+use server::workspace::WorkspaceManager;
+
+#[tokio::test]
+async fn the_challenges_in_the_repository_pass_validation() {
+    let challenges = load_challenges(&challenges_dir()).unwrap();
+    let fake = FakeNargo::new();
+    // The hidden tests only pass against the reference solutions, and each vector proves or
+    // fails as it expects to
+    fake.on("test", FakeRun::failure(1).stderr("error: Failed assertion"));
+    for challenge in challenges.iter() {
+        let solution = challenge.solution.as_ref().expect("every challenge has a solution.nr");
+        let id = challenge.id;
+        assert!(solution.prover_inputs.is_some(), "challenge {} has no solution.toml", id);
+        fake.on_matching("test", &solution.code, FakeRun::success());
+        for vector in &challenge.vectors {
+            let inputs = toml::to_string(&toml::Value::Table(vector.inputs.clone())).unwrap();
+            let run = match (vector.expect, &vector.return_value) {
+                (Expectation::Failure, _) => FakeRun::failure(1).stderr("error: Failed assertion"),
+                (Expectation::Success, Some(value)) => {
+                    FakeRun::success().write_file("Verifier.toml", &format!("return = {}\n", value))
+                }
+                (Expectation::Success, None) => FakeRun::success(),
+            };
+            fake.on_matching("prove", &inputs, run);
+        }
+    }
+    let root = temp_dir("workspaces");
+    let workspaces = WorkspaceManager::new(&fake, &root, 0).await.unwrap();
+
+    assert!(validate_challenges(&fake, &workspaces, &challenges).await);
+}
@@ -0,0 +1,102 @@
+mod common;
+
+use common::{ temp_dir, wait_until_removed };
+use common::fake::FakeNargo;
+use server::error::ApiError;
+use server::toolchain::NargoCommand;
+use server::workspace::{ WorkspaceError, WorkspaceManager };
+
+#[tokio::test]
+async fn a_workspace_is_removed_when_it_is_dropped() {
+    let fake = FakeNargo::new();
+    let root = temp_dir("workspaces");
+    let workspaces = WorkspaceManager::new(&fake, &root, 0).await.unwrap();
+
+    let workspace = workspaces.create().await.unwrap();
+    let dir = workspace.dir().to_path_buf();
+    assert!(workspace.project().join("src/main.nr").exists());
+    assert!(workspace.project().join("Nargo.toml").exists());
+    drop(workspace);
+    wait_until_removed(&dir);
+
+    // nargo new only ran once, for the template
+    let news = fake
+        .calls()
+        .iter()
+        .filter(|call| matches!(call.command, NargoCommand::New(_)))
+        .count();
+    assert_eq!(news, 1);
+}
+
+#[tokio::test]
+async fn stale_directories_are_swept_at_startup() {
+    let root = temp_dir("workspaces");
+    let stale = root.join(uuid::Uuid::new_v4().to_string());
+    let stale_template = root.join(format!("template-{}", uuid::Uuid::new_v4()));
+    let other = root.join("not-a-workspace");
+    for dir in [&stale, &stale_template, &other] {
+        std::fs::create_dir_all(dir.join("project")).unwrap();
+    }
+
+    let _workspaces = WorkspaceManager::new(&FakeNargo::new(), &root, 0).await.unwrap();
+    assert!(!stale.exists());
+    assert!(!stale_template.exists());
+    assert!(other.exists());
+}
+
+#[tokio::test]
+async fn the_janitor_removes_leftovers_but_not_workspaces_in_use() {
+    let root = temp_dir("workspaces");
+    let workspaces = WorkspaceManager::new(&FakeNargo::new(), &root, 0).await.unwrap();
+    let workspace = workspaces.create().await.unwrap();
+    let leftover = root.join(uuid::Uuid::new_v4().to_string());
+    std::fs::create_dir_all(&leftover).unwrap();
+
+    workspaces.clean().await.unwrap();
+    assert!(!leftover.exists());
+    assert!(workspace.dir().exists());
+    // The template is kept, so workspaces can still be created
+    drop(workspaces.create().await.unwrap());
+}
+
+#[tokio::test]
+async fn the_janitor_keeps_workspaces_created_while_it_sweeps() {
+    let root = temp_dir("workspaces");
+    let workspaces = WorkspaceManager::new(&FakeNargo::new(), &root, 0).await.unwrap();
+    // Enough leftovers that the sweep reads the directory in several batches, so it comes
+    // across workspaces created after it started
+    for _ in 0..3000 {
+        std::fs::create_dir_all(root.join(uuid::Uuid::new_v4().to_string())).unwrap();
+    }
+
+    let create = async {
+        let mut created = vec![];
+        for _ in 0..20 {
+            created.push(workspaces.create().await.unwrap());
+            tokio::task::yield_now().await;
+        }
+        created
+    };
+    let (cleaned, created) = tokio::join!(workspaces.clean(), create);
+    cleaned.unwrap();
+    for workspace in &created {
+        assert!(workspace.project().join("src/main.nr").exists(), "{:?}", workspace.dir());
+    }
+}
+
+#[tokio::test]
+async fn new_workspaces_are_turned_away_once_the_disk_limit_is_reached() {
+    let root = temp_dir("workspaces");
+    let workspaces = WorkspaceManager::new(&FakeNargo::new(), &root, 1).await;
+    let workspaces = workspaces.unwrap();
+    assert!(workspaces.create().await.is_ok());
+
+    // The template alone uses more than a byte
+    workspaces.clean().await.unwrap();
+    let error = workspaces.create().await.err().unwrap();
+    assert!(matches!(error, WorkspaceError::DiskFull), "{}", error);
+    // Clients are told to come back later, rather than that the server failed
+    let error = ApiError::from(error);
+    assert_eq!(error.status(), 503);
+    assert_eq!(error.code(), "disk_full");
+}